@@ -0,0 +1,407 @@
+use anyhow::{Context, Result};
+use flate2::read::MultiGzDecoder;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use crate::file_reader::{FileId, StartPosition, TailerState, decode_line, started_since};
+
+/// Compression format of a log file, detected from its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Plain text (e.g. `access.log.1` left uncompressed by `delaycompress`)
+    None,
+    /// gzip (`.gz`)
+    Gzip,
+    /// Zstandard (`.zst`)
+    Zstd,
+}
+
+impl Compression {
+    /// Detect the compression format from the file extension
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// Reader that streams lines from a rotated (optionally compressed) log archive
+/// Unlike LogTailer, an archive is read once from start to end and never followed
+pub struct ArchiveReader {
+    path: PathBuf,
+    reader: Box<dyn BufRead + Send>,
+    start: StartPosition,
+    /// Lines kept for `--last-lines`/`--last-bytes`, once the archive has been read
+    tail: Option<VecDeque<(String, bool)>>,
+    state: Option<TailerState>,
+    invalid_utf8: usize,
+}
+
+impl ArchiveReader {
+    /// Open an archive, choosing the decompressor from the file extension
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::with_start(path, StartPosition::Beginning)
+    }
+
+    /// Open an archive that yields only the lines selected by `start`
+    /// A compressed stream cannot seek, so the position is applied while reading: `Since`
+    /// skips lines until the first recent entry, `LastLines` and `LastBytes` keep a
+    /// window of the decompressed lines. `End` reads everything, like `Beginning`, since
+    /// an archive only holds existing entries.
+    pub fn with_start<P: AsRef<Path>>(path: P, start: StartPosition) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).context(format!("Failed to open file: {}", path.display()))?;
+        let size = file
+            .metadata()
+            .context("Failed to read file metadata")?
+            .len();
+        let state = FileId::of(&file).map(|file_id| TailerState {
+            file_id,
+            position: size,
+        });
+
+        let decoded: Box<dyn Read + Send> = match Compression::from_path(path) {
+            Compression::None => Box::new(file),
+            // Multi-member aware, so concatenated gzip streams are read completely
+            Compression::Gzip => Box::new(MultiGzDecoder::new(file)),
+            Compression::Zstd => Box::new(
+                zstd::Decoder::new(file)
+                    .context(format!("Failed to open zstd stream: {}", path.display()))?,
+            ),
+        };
+
+        Ok(ArchiveReader {
+            path: path.to_path_buf(),
+            reader: Box::new(BufReader::new(decoded)),
+            start,
+            tail: None,
+            state,
+            invalid_utf8: 0,
+        })
+    }
+
+    /// Path of the archive being read
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Identity and size of the archive file, for recording it in a checkpoint
+    /// The position is the whole (compressed) size, as an archive is only read whole.
+    /// None if the platform cannot identify files
+    pub fn state(&self) -> Option<TailerState> {
+        self.state
+    }
+
+    /// Read up to `max_lines` non-empty lines from the archive
+    /// Returns an empty vector once the whole archive has been read
+    pub fn read_lines(&mut self, max_lines: usize) -> Result<Vec<String>> {
+        if let StartPosition::LastLines(_) | StartPosition::LastBytes(_) = self.start {
+            self.read_tail()?;
+        }
+
+        let mut lines = Vec::new();
+        while lines.len() < max_lines {
+            let next = match self.tail {
+                Some(ref mut tail) => tail.pop_front(),
+                None => self.next_line()?,
+            };
+            let Some((line, invalid)) = next else {
+                // End of archive reached
+                break;
+            };
+
+            // Everything after the first recent entry is read
+            if let StartPosition::Since(since) = self.start {
+                if !started_since(&line, since) {
+                    continue;
+                }
+                self.start = StartPosition::Beginning;
+            }
+
+            if invalid {
+                self.invalid_utf8 += 1;
+            }
+            lines.push(line);
+        }
+
+        Ok(lines)
+    }
+
+    /// Number of lines containing invalid UTF-8 since the last call
+    /// Such lines are still returned by `read_lines`, decoded lossily
+    pub fn take_invalid_utf8(&mut self) -> usize {
+        std::mem::take(&mut self.invalid_utf8)
+    }
+
+    /// Read the whole archive, keeping the last lines selected by the start position
+    /// Bytes are counted as each trimmed line plus its newline
+    fn read_tail(&mut self) -> Result<()> {
+        let mut tail = VecDeque::new();
+        let mut bytes = 0;
+
+        while let Some((line, invalid)) = self.next_line()? {
+            bytes += line.len() as u64 + 1;
+            tail.push_back((line, invalid));
+            match self.start {
+                StartPosition::LastLines(n) if tail.len() > n => {
+                    if let Some((line, _)) = tail.pop_front() {
+                        bytes -= line.len() as u64 + 1;
+                    }
+                }
+                StartPosition::LastBytes(n) => {
+                    while bytes > n
+                        && let Some((line, _)) = tail.pop_front()
+                    {
+                        bytes -= line.len() as u64 + 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.tail = Some(tail);
+        self.start = StartPosition::Beginning;
+        Ok(())
+    }
+
+    /// Read the next non-empty line, trimmed, and whether it contained invalid UTF-8
+    fn next_line(&mut self) -> Result<Option<(String, bool)>> {
+        loop {
+            let mut bytes = Vec::new();
+            let bytes_read = self
+                .reader
+                .read_until(b'\n', &mut bytes)
+                .context(format!("Failed to read line from {}", self.path.display()))?;
+
+            if bytes_read == 0 {
+                return Ok(None);
+            }
+
+            // Trim whitespace and skip empty lines
+            let (line, invalid) = decode_line(bytes);
+            let trimmed = line.trim();
+            if !trimmed.is_empty() {
+                return Ok(Some((trimmed.to_string(), invalid)));
+            }
+        }
+    }
+}
+
+/// Sort archives oldest first by modification time
+/// Compressing or renaming a rotated log keeps its modification time, so this is the
+/// order the entries were written in. Ties keep their current order.
+pub fn sort_oldest_first(paths: &mut [PathBuf]) {
+    paths.sort_by_cached_key(|path| {
+        std::fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .ok()
+    });
+}
+
+/// Find the rotated archives of a live log file, oldest first
+/// Matches `<name>.<N>`, `<name>.<N>.gz` and `<name>.<N>.zst` next to the live file,
+/// so `access.log` yields `access.log.3.gz`, `access.log.2.gz`, `access.log.1`.
+/// Archives are ordered by modification time, the rotation number breaking ties.
+pub fn rotation_set(live_path: &Path) -> Result<Vec<PathBuf>> {
+    let file_name = match live_path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => return Ok(Vec::new()),
+    };
+    let dir = match live_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let prefix = format!("{}.", file_name);
+
+    let mut archives: Vec<(u32, PathBuf)> = Vec::new();
+    for entry in std::fs::read_dir(dir)
+        .context(format!("Failed to list rotated logs in {}", dir.display()))?
+    {
+        let entry = entry.context("Failed to read directory entry")?;
+        let name = entry.file_name();
+        let Some(suffix) = name.to_str().and_then(|name| name.strip_prefix(&prefix)) else {
+            continue;
+        };
+
+        // Strip the compression extension, what remains must be the rotation number
+        let number = suffix
+            .strip_suffix(".gz")
+            .or_else(|| suffix.strip_suffix(".zst"))
+            .unwrap_or(suffix);
+        if let Ok(number) = number.parse::<u32>() {
+            archives.push((number, dir.join(&name)));
+        }
+    }
+
+    // Highest rotation number is the oldest
+    archives.sort_by_key(|(number, _)| std::cmp::Reverse(*number));
+    let mut paths: Vec<PathBuf> = archives.into_iter().map(|(_, path)| path).collect();
+    sort_oldest_first(&mut paths);
+
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression as GzLevel;
+    use flate2::write::GzEncoder;
+    use std::fs;
+    use std::io::Write;
+    use std::time::{Duration, SystemTime};
+
+    fn read_all(path: &Path) -> Vec<String> {
+        read_from(path, StartPosition::Beginning)
+    }
+
+    fn read_from(path: &Path, start: StartPosition) -> Vec<String> {
+        let mut reader = ArchiveReader::with_start(path, start).unwrap();
+        let mut all = Vec::new();
+        loop {
+            let lines = reader.read_lines(2).unwrap();
+            if lines.is_empty() {
+                break;
+            }
+            all.extend(lines);
+        }
+        all
+    }
+
+    #[test]
+    fn test_read_gzip_and_zstd_archives() {
+        let dir = "test_archive_reader_dir";
+        fs::create_dir_all(dir).unwrap();
+        let content = "line 1\nline 2\n\nline 3\n";
+
+        let gz_path = PathBuf::from(format!("{}/access.log.1.gz", dir));
+        let mut encoder = GzEncoder::new(fs::File::create(&gz_path).unwrap(), GzLevel::fast());
+        encoder.write_all(content.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        let zst_path = PathBuf::from(format!("{}/access.log.2.zst", dir));
+        let compressed = zstd::encode_all(content.as_bytes(), 0).unwrap();
+        fs::write(&zst_path, compressed).unwrap();
+
+        let expected = vec!["line 1", "line 2", "line 3"];
+        assert_eq!(read_all(&gz_path), expected);
+        assert_eq!(read_all(&zst_path), expected);
+
+        // Clean up
+        fs::remove_dir_all(dir).ok();
+    }
+
+    fn set_modified(path: &str, time: SystemTime) {
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_archive_start_positions() {
+        let path = PathBuf::from("test_archive_start.log.1.gz");
+        let lines: Vec<String> = (0..5)
+            .map(|minute| {
+                format!(
+                    r#"{{"ClientHost":"10.0.0.1","StartUTC":"2024-01-15T10:0{}:00Z"}}"#,
+                    minute
+                )
+            })
+            .collect();
+        let mut encoder = GzEncoder::new(fs::File::create(&path).unwrap(), GzLevel::fast());
+        encoder.write_all(lines.join("\n").as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        assert_eq!(read_from(&path, StartPosition::End), lines);
+        let since = chrono::DateTime::parse_from_rfc3339("2024-01-15T10:02:30Z")
+            .unwrap()
+            .to_utc();
+        assert_eq!(read_from(&path, StartPosition::Since(since)), lines[3..]);
+        assert_eq!(read_from(&path, StartPosition::LastLines(2)), lines[3..]);
+        assert_eq!(read_from(&path, StartPosition::LastLines(0)), lines[5..]);
+        let line_bytes = lines[0].len() as u64 + 1;
+        assert_eq!(
+            read_from(&path, StartPosition::LastBytes(line_bytes * 3 - 1)),
+            lines[3..]
+        );
+
+        // Clean up
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_sort_oldest_first_uses_modification_time() {
+        let dir = "test_sort_oldest_first_dir";
+        fs::create_dir_all(dir).unwrap();
+        let now = SystemTime::now();
+        for (name, age) in [("a.log.gz", 0), ("b.log.gz", 120), ("c.log.gz", 60)] {
+            let path = format!("{}/{}", dir, name);
+            fs::File::create(&path).unwrap();
+            set_modified(&path, now - Duration::from_secs(age));
+        }
+
+        let mut paths: Vec<PathBuf> = ["a.log.gz", "b.log.gz", "c.log.gz"]
+            .iter()
+            .map(|name| PathBuf::from(format!("{}/{}", dir, name)))
+            .collect();
+        sort_oldest_first(&mut paths);
+        let names: Vec<String> = paths
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, vec!["b.log.gz", "c.log.gz", "a.log.gz"]);
+
+        // Clean up
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_rotation_set_is_oldest_first() {
+        let dir = "test_rotation_set_dir";
+        fs::create_dir_all(dir).unwrap();
+        for name in [
+            "access.log",
+            "access.log.1",
+            "access.log.2.gz",
+            "access.log.10.zst",
+            "access.log.3.gz",
+            "access.log.bak",
+            "other.log.1.gz",
+        ] {
+            fs::File::create(format!("{}/{}", dir, name)).unwrap();
+        }
+        // Equal modification times fall back to the rotation number
+        let now = SystemTime::now();
+        for name in ["access.log.1", "access.log.2.gz", "access.log.3.gz"] {
+            set_modified(&format!("{}/{}", dir, name), now);
+        }
+        set_modified(
+            &format!("{}/access.log.10.zst", dir),
+            now - Duration::from_secs(60),
+        );
+
+        let set = rotation_set(&PathBuf::from(format!("{}/access.log", dir))).unwrap();
+        let names: Vec<String> = set
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "access.log.10.zst",
+                "access.log.3.gz",
+                "access.log.2.gz",
+                "access.log.1"
+            ]
+        );
+
+        // Clean up
+        fs::remove_dir_all(dir).ok();
+    }
+}
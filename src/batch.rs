@@ -0,0 +1,423 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::archive_reader::{ArchiveReader, Compression};
+use crate::client_ip::IpResolver;
+use crate::envelope::Envelope;
+use crate::file_reader::LineBuffer;
+use crate::format::LogFormat;
+use crate::path_normalizer::PathNormalizer;
+use crate::pipeline::Pipeline;
+use crate::query_stats::QueryLimits;
+use crate::statistics::StatsCollector;
+
+/// Lines handed to the pipeline at a time by each worker
+const BATCH_LINES: usize = 4096;
+
+/// Throughput figures of a batch analysis run
+#[derive(Debug, Clone, Copy)]
+pub struct BatchReport {
+    /// Bytes read (decompressed size for archives)
+    pub bytes: u64,
+    /// Non-empty lines read
+    pub lines: usize,
+    /// Worker threads used
+    pub threads: usize,
+    /// Wall-clock time of the run
+    pub elapsed: Duration,
+}
+
+impl BatchReport {
+    /// Render the throughput summary printed after an analysis
+    pub fn render(&self) -> String {
+        let secs = self.elapsed.as_secs_f64().max(f64::EPSILON);
+        format!(
+            "Analyzed {} lines ({:.1} MB) in {:.2}s on {} threads: {:.0} lines/s, {:.1} MB/s",
+            self.lines,
+            self.bytes as f64 / 1_000_000.0,
+            secs,
+            self.threads,
+            self.lines as f64 / secs,
+            self.bytes as f64 / 1_000_000.0 / secs
+        )
+    }
+}
+
+/// One unit of work: a newline-aligned byte range of a plain file, or a whole archive
+enum Chunk {
+    Range { path: PathBuf, start: u64, end: u64 },
+    Archive(PathBuf),
+}
+
+/// Partial result of one worker
+struct ChunkResult {
+    stats: StatsCollector,
+    bytes: u64,
+    lines: usize,
+}
+
+/// Offline analyzer for large historical logs
+/// Splits plain files into newline-aligned chunks, parses and aggregates the chunks on
+/// all cores into partial `StatsCollector`s, and merges them at the end. Compressed
+/// archives cannot be split and are processed as one chunk each.
+pub struct BatchAnalyzer {
+    threads: usize,
+    envelope: Envelope,
+    format: Option<&'static dyn LogFormat>,
+    resolver: IpResolver,
+    normalizer: Option<PathNormalizer>,
+    query_limits: QueryLimits,
+}
+
+impl BatchAnalyzer {
+    /// Create an analyzer using `threads` workers (0 = one per CPU core)
+    pub fn new(threads: usize, envelope: Envelope, format: Option<&'static dyn LogFormat>) -> Self {
+        let threads = if threads == 0 {
+            thread::available_parallelism().map_or(1, |n| n.get())
+        } else {
+            threads
+        };
+        BatchAnalyzer {
+            threads,
+            envelope,
+            format,
+            resolver: IpResolver::default(),
+            normalizer: None,
+            query_limits: QueryLimits::default(),
+        }
+    }
+
+    /// Count requests under the client IP determined by `resolver`
+    pub fn resolver(mut self, resolver: IpResolver) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Count paths by their route template
+    pub fn normalizer(mut self, normalizer: PathNormalizer) -> Self {
+        self.normalizer = Some(normalizer);
+        self
+    }
+
+    /// Track as many query parameters and values as `limits` allow
+    pub fn query_limits(mut self, limits: QueryLimits) -> Self {
+        self.query_limits = limits;
+        self
+    }
+
+    /// Empty statistics configured like the final result
+    fn new_stats(&self) -> StatsCollector {
+        let stats = StatsCollector::with_resolver(self.resolver.clone())
+            .with_query_limits(self.query_limits);
+        match self.normalizer {
+            Some(ref normalizer) => stats.with_normalizer(normalizer.clone()),
+            None => stats,
+        }
+    }
+
+    /// Analyze the given files and return the merged statistics with throughput figures
+    pub fn analyze(&self, paths: &[PathBuf]) -> Result<(StatsCollector, BatchReport)> {
+        let started = Instant::now();
+
+        let mut chunks = Vec::new();
+        for path in paths {
+            if Compression::from_path(path) == Compression::None {
+                chunks.extend(split_file(path, self.threads, self.envelope)?);
+            } else {
+                chunks.push(Chunk::Archive(path.clone()));
+            }
+        }
+
+        // Workers take the next unclaimed chunk until none are left, so a core that
+        // finishes early (e.g. on a small archive) picks up more work
+        let workers = self.threads.min(chunks.len()).max(1);
+        let next_chunk = AtomicUsize::new(0);
+        let results: Vec<Result<Vec<(usize, ChunkResult)>>> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    let (chunks, next_chunk) = (&chunks, &next_chunk);
+                    scope.spawn(move || {
+                        let mut results = Vec::new();
+                        loop {
+                            let index = next_chunk.fetch_add(1, Ordering::Relaxed);
+                            let Some(chunk) = chunks.get(index) else {
+                                return Ok(results);
+                            };
+                            results.push((index, analyze_chunk(chunk, self)?));
+                        }
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("batch worker panicked"))
+                .collect()
+        });
+
+        // Merge in chunk order, so the result does not depend on scheduling
+        let mut partials = Vec::with_capacity(chunks.len());
+        for result in results {
+            partials.extend(result?);
+        }
+        partials.sort_by_key(|(index, _)| *index);
+
+        let mut stats = self.new_stats();
+        let mut bytes = 0;
+        let mut lines = 0;
+        for (_, partial) in partials {
+            stats.merge(partial.stats);
+            bytes += partial.bytes;
+            lines += partial.lines;
+        }
+
+        let report = BatchReport {
+            bytes,
+            lines,
+            threads: workers,
+            elapsed: started.elapsed(),
+        };
+        Ok((stats, report))
+    }
+}
+
+/// Split a plain file into up to `parts` byte ranges that start and end on line boundaries
+/// With a container log envelope, ranges also end on record boundaries: each chunk has
+/// its own decoder, which could not join a split record across two chunks
+fn split_file(path: &Path, parts: usize, envelope: Envelope) -> Result<Vec<Chunk>> {
+    let file = File::open(path).context(format!("Failed to open file: {}", path.display()))?;
+    let size = file
+        .metadata()
+        .context("Failed to read file metadata")?
+        .len();
+    let mut reader = BufReader::new(file);
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    for part in 1..=parts as u64 {
+        if start >= size {
+            break;
+        }
+
+        // Move the nominal boundary forward to just past the next newline
+        let mut end = size * part / parts as u64;
+        if end < size && end > start {
+            reader
+                .seek(SeekFrom::Start(end))
+                .context("Failed to seek to chunk boundary")?;
+            let mut line = Vec::new();
+            end += reader
+                .read_until(b'\n', &mut line)
+                .context("Failed to find chunk boundary")? as u64;
+
+            // The line before the boundary was only partly read, so move on to the end
+            // of the first whole line that does not continue into the next one
+            if envelope != Envelope::None {
+                loop {
+                    line.clear();
+                    let bytes_read = reader
+                        .read_until(b'\n', &mut line)
+                        .context("Failed to find chunk boundary")?;
+                    end += bytes_read as u64;
+                    let text = String::from_utf8_lossy(&line);
+                    if bytes_read == 0 || !envelope.is_partial(text.trim_end()) {
+                        break;
+                    }
+                }
+            }
+        }
+        let end = end.max(start).min(size);
+        if end == start {
+            continue;
+        }
+
+        chunks.push(Chunk::Range {
+            path: path.to_path_buf(),
+            start,
+            end,
+        });
+        start = end;
+    }
+
+    Ok(chunks)
+}
+
+/// Parse and aggregate one chunk into its own statistics
+fn analyze_chunk(chunk: &Chunk, analyzer: &BatchAnalyzer) -> Result<ChunkResult> {
+    let mut pipeline = Pipeline::new(analyzer.envelope, analyzer.format).quiet();
+    let mut result = ChunkResult {
+        stats: analyzer.new_stats(),
+        bytes: 0,
+        lines: 0,
+    };
+
+    match chunk {
+        Chunk::Range { path, start, end } => {
+            let source = path.display().to_string();
+            let mut file =
+                File::open(path).context(format!("Failed to open file: {}", path.display()))?;
+            file.seek(SeekFrom::Start(*start))
+                .context("Failed to seek to chunk start")?;
+            let mut reader = BufReader::with_capacity(1 << 20, file);
+
+            // Lines are read into reused buffers, which the pipeline only borrows
+            let mut bytes = Vec::new();
+            let mut lines = LineBuffer::default();
+            let mut position = *start;
+            while position < *end {
+                bytes.clear();
+                let bytes_read = reader
+                    .read_until(b'\n', &mut bytes)
+                    .context(format!("Failed to read line from {}", path.display()))?;
+                if bytes_read == 0 {
+                    break;
+                }
+                position += bytes_read as u64;
+                result.bytes += bytes_read as u64;
+
+                if lines.push_bytes(&bytes) {
+                    result.stats.record_invalid_utf8(1);
+                }
+                if lines.len() == BATCH_LINES {
+                    result.lines += lines.len();
+                    pipeline.ingest_lines(&mut result.stats, &source, lines.lines());
+                    lines.clear();
+                }
+            }
+            result.lines += lines.len();
+            pipeline.ingest_lines(&mut result.stats, &source, lines.lines());
+        }
+        Chunk::Archive(path) => {
+            let source = path.display().to_string();
+            let mut reader = ArchiveReader::open(path)?;
+            loop {
+                let lines = reader.read_lines(BATCH_LINES)?;
+                if lines.is_empty() {
+                    break;
+                }
+                result.stats.record_invalid_utf8(reader.take_invalid_utf8());
+                result.lines += lines.len();
+                // Approximate decompressed size, line terminators included
+                result.bytes += lines.iter().map(|line| line.len() as u64 + 1).sum::<u64>();
+                pipeline.ingest_lines(&mut result.stats, &source, &lines);
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+
+    #[test]
+    fn test_batch_analyzer_matches_sequential_result() {
+        let test_file = PathBuf::from("test_batch_analyzer.log");
+
+        let mut file = fs::File::create(&test_file).unwrap();
+        for i in 0..1000 {
+            writeln!(
+                file,
+                r#"{{"ClientHost":"10.0.0.{}","RequestPath":"/path/{}"}}"#,
+                i % 7,
+                i % 3
+            )
+            .unwrap();
+        }
+        writeln!(file, "not json").unwrap();
+        drop(file);
+
+        // Chunks cover the file exactly, on line boundaries
+        let chunks = split_file(&test_file, 4, Envelope::Auto).unwrap();
+        assert_eq!(chunks.len(), 4);
+        let size = fs::metadata(&test_file).unwrap().len();
+        let mut expected_start = 0;
+        for chunk in &chunks {
+            let Chunk::Range { start, end, .. } = chunk else {
+                panic!("plain files are split into ranges");
+            };
+            assert_eq!(*start, expected_start);
+            expected_start = *end;
+        }
+        assert_eq!(expected_start, size);
+
+        let (stats, report) = BatchAnalyzer::new(4, Envelope::Auto, None)
+            .analyze(std::slice::from_ref(&test_file))
+            .unwrap();
+        assert_eq!(stats.total_requests(), 1000);
+        assert_eq!(stats.unique_ips(), 7);
+        assert_eq!(stats.errors().malformed, 1);
+        assert_eq!(report.lines, 1001);
+        assert_eq!(report.bytes, size);
+
+        // Six of the seven IPs tie at 143 requests, the last one has 142
+        let top = stats.get_top_ips(7);
+        assert_eq!(top[0].1.request_count, 143);
+        assert_eq!(top[6].1.request_count, 142);
+
+        // Clean up
+        fs::remove_file(&test_file).ok();
+    }
+
+    #[test]
+    fn test_split_small_file_into_fewer_chunks() {
+        let test_file = PathBuf::from("test_batch_split_small.log");
+        fs::write(&test_file, "only one line\n").unwrap();
+
+        let chunks = split_file(&test_file, 8, Envelope::Auto).unwrap();
+        assert_eq!(chunks.len(), 1);
+
+        // Clean up
+        fs::remove_file(&test_file).ok();
+    }
+
+    #[test]
+    fn test_split_records_are_not_cut_at_chunk_boundaries() {
+        let test_file = PathBuf::from("test_batch_split_records.log");
+
+        // Every entry is split into two CRI records, so most nominal boundaries fall
+        // between the halves of a record
+        let mut file = fs::File::create(&test_file).unwrap();
+        for i in 0..200 {
+            writeln!(file, r#"2024-01-01T00:00:00Z stdout P {{"ClientHost":"#).unwrap();
+            writeln!(
+                file,
+                r#"2024-01-01T00:00:00Z stdout F "10.0.0.{}"}}"#,
+                i % 5
+            )
+            .unwrap();
+        }
+        drop(file);
+
+        for envelope in [Envelope::Cri, Envelope::Auto] {
+            for chunk in split_file(&test_file, 7, envelope).unwrap() {
+                let Chunk::Range { start, .. } = chunk else {
+                    panic!("plain files are split into ranges");
+                };
+                let mut reader = BufReader::new(File::open(&test_file).unwrap());
+                reader.seek(SeekFrom::Start(start)).unwrap();
+                let mut first = String::new();
+                reader.read_line(&mut first).unwrap();
+                assert!(first.contains(" P "), "chunk starts mid-record: {}", first);
+            }
+
+            let (stats, _) = BatchAnalyzer::new(7, envelope, None)
+                .analyze(std::slice::from_ref(&test_file))
+                .unwrap();
+            assert_eq!(stats.total_requests(), 200);
+            assert_eq!(stats.unique_ips(), 5);
+            assert_eq!(stats.errors().malformed, 0);
+        }
+
+        // Clean up
+        fs::remove_file(&test_file).ok();
+    }
+}
@@ -0,0 +1,115 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::file_reader::TailerState;
+
+/// Checkpoint file recording how far each tailed log has been read
+/// Stored as JSON mapping each log's absolute path to its file identity and offset,
+/// so a restarted monitor can resume instead of losing the requests written in between
+pub struct Checkpoint {
+    path: PathBuf,
+    files: BTreeMap<String, TailerState>,
+}
+
+impl Checkpoint {
+    /// Load the checkpoint from `path`, starting empty if it does not exist yet
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let files = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .context(format!("Invalid checkpoint file: {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => {
+                return Err(e).context(format!(
+                    "Failed to read checkpoint file: {}",
+                    path.display()
+                ));
+            }
+        };
+
+        Ok(Checkpoint {
+            path: path.to_path_buf(),
+            files,
+        })
+    }
+
+    /// Saved state for a log file, if any
+    pub fn get(&self, log_path: &Path) -> Option<TailerState> {
+        self.files.get(&Self::key(log_path)).copied()
+    }
+
+    /// Record the state of a log file
+    /// Returns true if it differs from what was recorded before
+    pub fn update(&mut self, log_path: &Path, state: TailerState) -> bool {
+        self.files.insert(Self::key(log_path), state) != Some(state)
+    }
+
+    /// Write the checkpoint to disk
+    /// Writes a temporary file and renames it over the old one, so a crash mid-write
+    /// never leaves a corrupt checkpoint behind
+    pub fn save(&self) -> Result<()> {
+        let content =
+            serde_json::to_string_pretty(&self.files).context("Failed to serialize checkpoint")?;
+
+        let mut tmp_name = self.path.as_os_str().to_owned();
+        tmp_name.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_name);
+
+        fs::write(&tmp_path, content).context(format!(
+            "Failed to write checkpoint file: {}",
+            tmp_path.display()
+        ))?;
+        fs::rename(&tmp_path, &self.path).context(format!(
+            "Failed to replace checkpoint file: {}",
+            self.path.display()
+        ))?;
+
+        Ok(())
+    }
+
+    /// Key a log file by its absolute path, so relative paths from different
+    /// working directories don't collide
+    fn key(log_path: &Path) -> String {
+        std::path::absolute(log_path)
+            .unwrap_or_else(|_| log_path.to_path_buf())
+            .display()
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_reader::{LogTailer, StartPosition};
+    use std::io::Write;
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let state_file = "test_checkpoint_round_trip.json";
+        let log_file = "test_checkpoint_round_trip.log";
+
+        let mut file = fs::File::create(log_file).unwrap();
+        writeln!(file, "line 1").unwrap();
+        drop(file);
+        let tailer = LogTailer::with_start(log_file, StartPosition::End).unwrap();
+        let state = tailer.state().unwrap();
+
+        // Missing checkpoint file starts empty
+        let mut checkpoint = Checkpoint::load(state_file).unwrap();
+        assert!(checkpoint.get(Path::new(log_file)).is_none());
+
+        assert!(checkpoint.update(Path::new(log_file), state));
+        assert!(!checkpoint.update(Path::new(log_file), state));
+        checkpoint.save().unwrap();
+
+        let checkpoint = Checkpoint::load(state_file).unwrap();
+        assert_eq!(checkpoint.get(Path::new(log_file)), Some(state));
+        assert_eq!(state.position, 7);
+
+        // Clean up
+        fs::remove_file(state_file).ok();
+        fs::remove_file(log_file).ok();
+    }
+}
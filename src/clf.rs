@@ -0,0 +1,202 @@
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+
+use crate::headers::HeaderValue;
+use crate::log_entry::TraefikLogEntry;
+
+/// Parse one line of Traefik's CLF (common) access log format
+/// Layout: `<ClientHost> - <ClientUsername> [<StartLocal>] "<Method> <Path> <Protocol>"
+/// <DownstreamStatus> <DownstreamContentSize> "<Referer>" "<User-Agent>" <RequestCount>
+/// "<RouterName>" "<ServiceURL>" <Duration>ms`
+/// The fields after the user agent are Traefik additions and may be missing; "-" marks
+/// an empty value anywhere
+pub fn parse_clf_line(line: &str) -> Result<TraefikLogEntry> {
+    let fields = split_fields(line)?;
+    if fields.len() < 7 {
+        bail!("Expected at least 7 CLF fields, found {}", fields.len());
+    }
+    let field = |index: usize| -> Option<&str> {
+        fields
+            .get(index)
+            .map(String::as_str)
+            .filter(|value| !value.is_empty() && *value != "-")
+    };
+
+    let mut entry = TraefikLogEntry {
+        client_host: field(0).map(str::to_string),
+        client_username: field(2).map(str::to_string),
+        ..Default::default()
+    };
+
+    if let Some(time) = field(3) {
+        let time = DateTime::parse_from_str(time, "%d/%b/%Y:%H:%M:%S %z")
+            .context(format!("Invalid CLF timestamp: {}", time))?;
+        entry.start_local = Some(time);
+        entry.start_utc = Some(time.with_timezone(&Utc));
+    }
+
+    // "GET /path?q=1 HTTP/1.1"; the path is whatever lies between method and protocol
+    if let Some(request) = field(4) {
+        let (method, rest) = request.split_once(' ').unwrap_or((request, ""));
+        let (path, protocol) = match rest.rsplit_once(' ') {
+            Some((path, protocol)) if protocol.starts_with("HTTP/") => (path, Some(protocol)),
+            _ => (rest, None),
+        };
+        entry.request_method = Some(method.to_string());
+        entry.request_path = Some(path.to_string()).filter(|path| !path.is_empty());
+        entry.request_protocol = protocol.map(str::to_string);
+    }
+
+    entry.downstream_status = parse_number(field(5), "status")?;
+    entry.downstream_content_size = parse_number(field(6), "content size")?;
+    for (name, index) in [("Referer", 7), ("User-Agent", 8)] {
+        if let Some(value) = field(index) {
+            let value = HeaderValue::Text(value.to_string());
+            entry.headers.request.insert(name, value);
+        }
+    }
+    entry.request_count = parse_number(field(9), "request count")?;
+    entry.router_name = field(10).map(str::to_string);
+    entry.service_url = field(11).map(str::to_string);
+
+    if let Some(duration) = field(12) {
+        let millis: u64 = duration
+            .strip_suffix("ms")
+            .and_then(|millis| millis.parse().ok())
+            .context(format!("Invalid CLF duration: {}", duration))?;
+        entry.duration = Some(millis * 1_000_000);
+    }
+
+    Ok(entry.with_timestamp())
+}
+
+/// Parse an optional numeric field
+fn parse_number<T: std::str::FromStr>(value: Option<&str>, name: &str) -> Result<Option<T>> {
+    value
+        .map(|value| {
+            value
+                .parse()
+                .ok()
+                .context(format!("Invalid CLF {}: {}", name, value))
+        })
+        .transpose()
+}
+
+/// Split a CLF line into its fields
+/// Fields are separated by spaces; `"..."` (with backslash escapes) and `[...]` are
+/// single fields with the delimiters removed
+fn split_fields(line: &str) -> Result<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut chars = line.trim().chars().peekable();
+
+    while let Some(&first) = chars.peek() {
+        if first == ' ' {
+            chars.next();
+            continue;
+        }
+
+        let mut field = String::new();
+        match first {
+            '"' => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => field.extend(chars.next()),
+                        Some(c) => field.push(c),
+                        None => bail!("Unterminated quoted field in CLF line"),
+                    }
+                }
+            }
+            '[' => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some(c) => field.push(c),
+                        None => bail!("Unterminated timestamp in CLF line"),
+                    }
+                }
+            }
+            _ => {
+                while let Some(c) = chars.next_if(|&c| c != ' ') {
+                    field.push(c);
+                }
+            }
+        }
+        fields.push(field);
+    }
+
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{JsonFormat, LogFormat};
+
+    #[test]
+    fn test_parse_traefik_clf_line() {
+        let line = r#"192.168.1.10 - alice [15/Jan/2024:11:30:00 +0100] "GET /api/users?page=2 HTTP/1.1" 200 1534 "https://example.com/" "Mozilla/5.0 (X11; Linux x86_64) \"quoted\"" 9153 "api@docker" "http://172.18.0.3:80" 12ms"#;
+        let entry = parse_clf_line(line).unwrap();
+
+        assert_eq!(entry.get_ip(), "192.168.1.10".parse().ok());
+        assert_eq!(entry.client_username.as_deref(), Some("alice"));
+        assert_eq!(
+            entry.start_utc.unwrap().to_rfc3339(),
+            "2024-01-15T10:30:00+00:00"
+        );
+        assert_eq!(entry.timestamp, entry.start_utc);
+        assert_eq!(
+            entry.start_local.unwrap().to_rfc3339(),
+            "2024-01-15T11:30:00+01:00"
+        );
+        assert_eq!(entry.request_method.as_deref(), Some("GET"));
+        assert_eq!(entry.get_path(), "/api/users?page=2");
+        assert_eq!(entry.request_protocol.as_deref(), Some("HTTP/1.1"));
+        assert_eq!(entry.downstream_status, Some(200));
+        assert_eq!(entry.downstream_content_size, Some(1534));
+        assert_eq!(
+            entry.headers.request.value("Referer"),
+            Some("https://example.com/")
+        );
+        assert_eq!(
+            entry.headers.request.value("user-agent"),
+            Some(r#"Mozilla/5.0 (X11; Linux x86_64) "quoted""#)
+        );
+        assert_eq!(entry.request_count, Some(9153));
+        assert_eq!(entry.router_name.as_deref(), Some("api@docker"));
+        assert_eq!(entry.service_url.as_deref(), Some("http://172.18.0.3:80"));
+        assert_eq!(entry.duration, Some(12_000_000));
+    }
+
+    #[test]
+    fn test_parse_clf_with_empty_and_missing_fields() {
+        // Plain CLF without Traefik's extra fields, "-" for unknown values
+        let line = r#"10.0.0.1 - - [15/Jan/2024:10:30:00 +0000] "GET / HTTP/2.0" 404 - "-" "-""#;
+        let entry = parse_clf_line(line).unwrap();
+        assert_eq!(entry.get_ip(), "10.0.0.1".parse().ok());
+        assert!(entry.client_username.is_none());
+        assert_eq!(entry.downstream_status, Some(404));
+        assert!(entry.downstream_content_size.is_none());
+        assert!(entry.headers.request.is_empty());
+        assert!(entry.router_name.is_none());
+        assert!(entry.duration.is_none());
+
+        assert!(parse_clf_line(r#"{"ClientHost":"10.0.0.1"}"#).is_err());
+        assert!(parse_clf_line(r#"10.0.0.1 - - [15/Jan/2024:10:30:00 +0000] "GET /"#).is_err());
+    }
+
+    #[test]
+    fn test_clf_and_json_produce_the_same_entry() {
+        let clf = r#"192.168.1.10 - alice [15/Jan/2024:11:30:00 +0100] "GET /api/users?page=2 HTTP/1.1" 200 1534 "https://example.com/" "curl/8.0" 9153 "api@docker" "http://172.18.0.3:80" 12ms"#;
+        let json = r#"{"ClientHost":"192.168.1.10","ClientUsername":"alice","StartUTC":"2024-01-15T10:30:00Z","StartLocal":"2024-01-15T11:30:00+01:00","RequestMethod":"GET","RequestPath":"/api/users?page=2","RequestProtocol":"HTTP/1.1","DownstreamStatus":200,"DownstreamContentSize":1534,"request_Referer":"https://example.com/","request_User-Agent":"curl/8.0","RequestCount":9153,"RouterName":"api@docker","ServiceURL":"http://172.18.0.3:80","Duration":12000000}"#;
+
+        let mut from_clf = parse_clf_line(clf).unwrap();
+        let mut from_json = JsonFormat.parse(json).unwrap();
+        // Headers are compared as maps, everything else field by field
+        let clf_headers = std::mem::take(&mut from_clf.headers);
+        assert_eq!(clf_headers, std::mem::take(&mut from_json.headers));
+        assert_eq!(format!("{:?}", from_clf), format!("{:?}", from_json));
+    }
+}
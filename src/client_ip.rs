@@ -0,0 +1,314 @@
+use anyhow::{Context, Result, bail};
+use std::fmt;
+use std::net::IpAddr;
+
+use crate::log_entry::{BorrowedEntry, TraefikLogEntry};
+
+/// An IP network in CIDR notation, e.g. 10.0.0.0/8 or 2001:db8::/32
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Parse a CIDR block; a bare address is a single-host block
+    pub fn parse(value: &str) -> Result<Self> {
+        let (address, prefix) = match value.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (value, None),
+        };
+        let network: IpAddr = address
+            .parse()
+            .context(format!("Invalid address in CIDR: {}", value))?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse()
+                .context(format!("Invalid prefix length in CIDR: {}", value))?,
+            None => max,
+        };
+        if prefix > max {
+            bail!("Prefix length of {} is longer than {} bits", value, max);
+        }
+
+        // Peer addresses are canonicalized by `parse_ip`, so an IPv4-mapped network
+        // (::ffff:10.0.0.0/104) must be matched as the IPv4 network it maps
+        if let IpAddr::V6(v6) = network
+            && let Some(v4) = v6.to_ipv4_mapped()
+        {
+            if prefix < 96 {
+                bail!(
+                    "{} covers more than IPv4-mapped addresses; give the IPv4 network instead",
+                    value
+                );
+            }
+            return Ok(Cidr {
+                network: IpAddr::V4(v4),
+                prefix: prefix - 96,
+            });
+        }
+        Ok(Cidr { network, prefix })
+    }
+
+    /// Whether an address lies inside this block
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(*ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(*ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// Request header listing the addresses a request was forwarded for
+pub const FORWARDED_FOR: &str = "X-Forwarded-For";
+/// Request header holding the client address set by a proxy
+pub const REAL_IP: &str = "X-Real-Ip";
+
+/// How the client IP of an entry was determined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum IpSource {
+    /// The address of the connection's peer (ClientHost / ClientAddr)
+    Peer,
+    /// The rightmost untrusted address in X-Forwarded-For
+    ForwardedFor,
+    /// The X-Real-Ip header
+    RealIp,
+}
+
+impl fmt::Display for IpSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            IpSource::Peer => "peer address",
+            IpSource::ForwardedFor => FORWARDED_FOR,
+            IpSource::RealIp => REAL_IP,
+        };
+        f.write_str(name)
+    }
+}
+
+/// Client IP of an entry and the method that produced it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedIp {
+    pub ip: IpAddr,
+    pub source: IpSource,
+}
+
+/// Resolves the real client IP of requests that passed through trusted proxies
+/// Forwarding headers are only believed when the peer itself is a trusted proxy, and
+/// X-Forwarded-For is walked from the right, skipping trusted proxies, so addresses a
+/// client put in the header itself are never used over the first untrusted hop
+#[derive(Debug, Clone, Default)]
+pub struct IpResolver {
+    trusted: Vec<Cidr>,
+}
+
+impl IpResolver {
+    /// Create a resolver trusting forwarding headers from the given proxy networks
+    /// With no networks, the peer address is always used
+    pub fn new(trusted: Vec<Cidr>) -> Self {
+        IpResolver { trusted }
+    }
+
+    /// Resolve the client IP of an entry, or None if it has no peer address
+    pub fn resolve(&self, entry: &TraefikLogEntry) -> Option<ResolvedIp> {
+        let headers = &entry.headers.request;
+        Some(self.resolve_from(
+            entry.get_ip()?,
+            headers.value(FORWARDED_FOR),
+            headers.value(REAL_IP),
+        ))
+    }
+
+    /// Resolve the client IP of a borrowed entry, or None if it has no peer address
+    pub fn resolve_borrowed(&self, entry: &BorrowedEntry) -> Option<ResolvedIp> {
+        Some(self.resolve_from(
+            entry.get_ip()?,
+            entry.forwarded_for.as_deref(),
+            entry.real_ip.as_deref(),
+        ))
+    }
+
+    /// Resolve the client IP from the peer address and the forwarding headers
+    fn resolve_from(
+        &self,
+        peer: IpAddr,
+        forwarded_for: Option<&str>,
+        real_ip: Option<&str>,
+    ) -> ResolvedIp {
+        if !self.is_trusted(&peer) {
+            return ResolvedIp {
+                ip: peer,
+                source: IpSource::Peer,
+            };
+        }
+
+        if let Some(forwarded) = forwarded_for {
+            // Unparseable entries are skipped; if every hop is trusted, the leftmost is the client
+            let hops: Vec<IpAddr> = forwarded.split(',').filter_map(parse_ip).collect();
+            let client = hops
+                .iter()
+                .rev()
+                .find(|ip| !self.is_trusted(ip))
+                .or(hops.first());
+            if let Some(client) = client {
+                return ResolvedIp {
+                    ip: *client,
+                    source: IpSource::ForwardedFor,
+                };
+            }
+        }
+
+        if let Some(real_ip) = real_ip.and_then(parse_ip) {
+            return ResolvedIp {
+                ip: real_ip,
+                source: IpSource::RealIp,
+            };
+        }
+
+        ResolvedIp {
+            ip: peer,
+            source: IpSource::Peer,
+        }
+    }
+
+    /// Whether an address belongs to a trusted proxy
+    fn is_trusted(&self, ip: &IpAddr) -> bool {
+        self.trusted.iter().any(|cidr| cidr.contains(ip))
+    }
+}
+
+/// Parse an address in any shape Traefik logs or proxies forward: `1.2.3.4`,
+/// `1.2.3.4:80`, `2001:db8::1`, `[2001:db8::1]` or `[2001:db8::1]:443`
+/// Zone IDs (`fe80::1%eth0`) are dropped and IPv4-mapped IPv6 addresses
+/// (`::ffff:1.2.3.4`) are returned as IPv4, so one client has one key
+pub fn parse_ip(value: &str) -> Option<IpAddr> {
+    let value = value.trim();
+    let host = match value.strip_prefix('[') {
+        Some(rest) => {
+            let (host, port) = rest.split_once(']')?;
+            if !port.is_empty() && !port.starts_with(':') {
+                return None;
+            }
+            host
+        }
+        // A single colon separates an IPv4 address from its port; IPv6 has several
+        None if value.matches(':').count() == 1 => value.split_once(':')?.0,
+        None => value,
+    };
+    let host = host.split_once('%').map_or(host, |(host, _zone)| host);
+    host.parse::<IpAddr>().ok().map(|ip| ip.to_canonical())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headers::HeaderValue;
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    fn entry(peer: &str, headers: &[(&str, &str)]) -> TraefikLogEntry {
+        let mut entry = TraefikLogEntry {
+            client_host: Some(peer.to_string()),
+            ..Default::default()
+        };
+        for (name, value) in headers {
+            let value = HeaderValue::Text(value.to_string());
+            entry.headers.request.insert(name, value);
+        }
+        entry
+    }
+
+    #[test]
+    fn test_cidr_contains() {
+        let cidr = Cidr::parse("10.1.0.0/16").unwrap();
+        assert!(cidr.contains(&"10.1.255.3".parse().unwrap()));
+        assert!(!cidr.contains(&"10.2.0.1".parse().unwrap()));
+        assert!(!cidr.contains(&"::ffff:10.1.0.1".parse().unwrap()));
+
+        let cidr = Cidr::parse("2001:db8::/32").unwrap();
+        assert!(cidr.contains(&"2001:db8:1::7".parse().unwrap()));
+        assert!(
+            Cidr::parse("0.0.0.0/0")
+                .unwrap()
+                .contains(&"8.8.8.8".parse().unwrap())
+        );
+        assert_eq!(
+            Cidr::parse("192.0.2.1").unwrap().to_string(),
+            "192.0.2.1/32"
+        );
+
+        // IPv4-mapped networks match the canonical IPv4 peer addresses
+        let cidr = Cidr::parse("::ffff:10.0.0.0/104").unwrap();
+        assert_eq!(cidr.to_string(), "10.0.0.0/8");
+        assert!(cidr.contains(&parse_ip("[::ffff:10.3.0.1]:443").unwrap()));
+        assert!(!cidr.contains(&parse_ip("11.0.0.1").unwrap()));
+        assert_eq!(
+            Cidr::parse("::ffff:192.0.2.1").unwrap().to_string(),
+            "192.0.2.1/32"
+        );
+        assert!(Cidr::parse("::ffff:0.0.0.0/80").is_err());
+
+        assert!(Cidr::parse("10.0.0.0/33").is_err());
+        assert!(Cidr::parse("10.0.0/8").is_err());
+        assert!(Cidr::parse("10.0.0.0/x").is_err());
+    }
+
+    #[test]
+    fn test_resolve_walks_forwarded_for_from_the_right() {
+        let resolver = IpResolver::new(vec![
+            Cidr::parse("10.0.0.0/8").unwrap(),
+            Cidr::parse("172.16.0.0/12").unwrap(),
+        ]);
+        let resolve = |entry: &TraefikLogEntry| resolver.resolve(entry).unwrap();
+
+        // The client-supplied 1.1.1.1 is not believed over the first untrusted hop
+        let forwarded = entry(
+            "10.0.0.5",
+            &[("X-Forwarded-For", "1.1.1.1, 203.0.113.7, 172.16.0.9:4711")],
+        );
+        assert_eq!(
+            resolve(&forwarded),
+            ResolvedIp {
+                ip: ip("203.0.113.7"),
+                source: IpSource::ForwardedFor
+            }
+        );
+
+        // Headers from an untrusted peer are ignored
+        let direct = entry("198.51.100.2", &[("X-Forwarded-For", "1.1.1.1")]);
+        assert_eq!(resolve(&direct).ip, ip("198.51.100.2"));
+        assert_eq!(resolve(&direct).source, IpSource::Peer);
+
+        let real_ip = entry("10.0.0.5", &[("X-Real-Ip", "[2001:db8::1]:443")]);
+        assert_eq!(resolve(&real_ip).ip, ip("2001:db8::1"));
+        assert_eq!(resolve(&real_ip).source, IpSource::RealIp);
+
+        let all_trusted = entry("10.0.0.5", &[("X-Forwarded-For", "10.9.9.9, 10.0.0.4")]);
+        assert_eq!(resolve(&all_trusted).ip, ip("10.9.9.9"));
+
+        let no_headers = entry("10.0.0.5", &[]);
+        assert_eq!(resolve(&no_headers).source, IpSource::Peer);
+
+        // Without trusted proxies the peer is always used
+        assert_eq!(
+            IpResolver::default().resolve(&forwarded).unwrap().ip,
+            ip("10.0.0.5")
+        );
+    }
+}
@@ -0,0 +1,591 @@
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::client_ip::Cidr;
+use crate::envelope::Envelope;
+use crate::file_reader::StartPosition;
+use crate::format::format_by_name;
+use crate::query_stats::QueryLimits;
+
+/// Default log file to monitor when no paths are given
+pub const DEFAULT_LOG_FILE_PATH: &str = "access.log";
+
+/// Default file name pattern for --watch-dir
+pub const DEFAULT_WATCH_PATTERN: &str = "*.log";
+
+/// Default time after which an unchanged file in a watched directory stops being tailed
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Default minimum time between screen redraws
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// Default number of IPs shown
+pub const DEFAULT_TOP_N: usize = 10;
+
+/// Usage text printed for `--help`
+pub const USAGE: &str = "\
+Usage: traefik_log_parser [OPTIONS] [PATH_OR_GLOB]...
+
+Monitors one or more Traefik JSON access logs and shows the top client IPs.
+Each argument is a file path or a glob pattern (e.g. \"logs/*/access.log\").
+Defaults to \"access.log\" in the current directory. Use \"-\" to read log lines
+from stdin (e.g. `docker logs -f traefik 2>&1 | traefik_log_parser -`); runtime
+commands are then read from the terminal. Rotated archives ending in
+.gz or .zst are read once instead of being tailed.
+
+Options:
+      --from-start        Analyze the existing content of each file, then keep tailing
+      --last-lines <N>    Analyze the last N lines of each file, then keep tailing
+      --last-bytes <N>    Analyze the last N bytes of each file, then keep tailing
+      --since <TIME>      Analyze entries with StartUTC at or after TIME (RFC 3339,
+                          e.g. 2024-01-15T10:00:00Z), then keep tailing
+      --rotated           Read each file's rotated archives first, oldest first
+                          (access.log.3.gz .. access.log.1), then the file itself
+                          from the beginning unless --since is given, which also
+                          applies to the archives
+      --watch-dir <DIR>   Tail every file in DIR matching --pattern, picking up new
+                          files as they are created (read from their beginning)
+      --pattern <GLOB>    File name pattern for --watch-dir (default \"*.log\")
+      --idle-timeout <S>  Stop tailing watched files that are deleted or unchanged
+                          for S seconds (default 300); they resume if they grow
+      --format <FORMAT>   Access log format: auto (default, detected per input from its
+                          first lines), json or clf
+      --envelope <KIND>   Container log envelope around each line: auto (default),
+                          none, docker (json-file) or cri (containerd/CRI-O)
+      --state-file <PATH> Save read offsets to PATH after every read and resume from
+                          them on startup (unless the file has been rotated since);
+                          archives read before are skipped
+      --refresh-ms <MS>   Minimum time between screen redraws (default 1000)
+      --poll              Poll files instead of using filesystem notifications
+                          (e.g. on network filesystems)
+      --fifo <PATH>       Read log lines from a named pipe, created if missing, and
+                          keep reading when the writer reconnects (Unix only)
+      --socket <PATH>     Listen on a Unix stream socket for log lines from any number
+                          of writers (Unix only)
+      --syslog <ADDR>     Receive syslog messages (RFC 5424 or 3164) over UDP and TCP on
+                          ADDR, e.g. 0.0.0.0:514; each sending host is its own source
+      --replay <FILE>     Replay an existing log (plain, .gz or .zst) through the live
+                          display, paced by each entry's StartUTC
+      --speed <X>         Replay speed multiplier, e.g. 1 (default), 10 or 0.5
+      --top <N>           Number of IPs shown (1-100, default 10)
+      --keep-query        Keep query strings when grouping request paths
+      --raw-paths         Count request paths exactly as logged instead of grouping
+                          them into route templates (/api/users/{id})
+      --query-limit <N>   Query parameter names, and most common values per name,
+                          tracked across all requests (default 1000)
+      --query-ip-limit <N>
+                          Query parameter names, and most common values per name,
+                          tracked for each IP (default 100)
+      --path-rules <FILE> JSON list of {\"pattern\": REGEX, \"template\": TEXT} rewrites
+                          applied to paths before the built-in placeholders
+      --trusted-proxy <CIDR>
+                          Take the client IP from X-Forwarded-For (rightmost address
+                          outside all trusted networks) or X-Real-Ip for requests from
+                          this proxy network, e.g. 10.0.0.0/8; repeatable or
+                          comma-separated
+      --analyze           Analyze the given files once on all cores, print the
+                          statistics and throughput, and exit instead of tailing;
+                          files are read whole, so start options, --rotated,
+                          other inputs and --state-file cannot be combined with it
+      --threads <N>       Worker threads for --analyze (default: one per core)
+  -h, --help              Print this help and exit";
+
+/// Runtime configuration parsed from command-line arguments
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Log file paths or glob patterns to tail
+    pub paths: Vec<String>,
+    /// Whether to read log lines from stdin (given as "-")
+    pub read_stdin: bool,
+    /// Where each tailer starts reading
+    pub start: StartPosition,
+    /// Whether to read the rotated archives of each live file before tailing it
+    pub include_rotated: bool,
+    /// Directory whose matching files are all tailed
+    pub watch_dir: Option<PathBuf>,
+    /// File name pattern for the watched directory
+    pub watch_pattern: String,
+    /// Time after which an unchanged watched file stops being tailed
+    pub idle_timeout: Duration,
+    /// Access log format name (None = detect per input)
+    pub format: Option<String>,
+    /// Container log envelope to unwrap
+    pub envelope: Envelope,
+    /// Checkpoint file for resuming tailers after a restart
+    pub state_file: Option<PathBuf>,
+    /// Minimum time between screen redraws
+    pub refresh_interval: Duration,
+    /// Whether to poll files instead of waiting for filesystem notifications
+    pub force_poll: bool,
+    /// Named pipe to read log lines from
+    pub fifo: Option<PathBuf>,
+    /// Unix stream socket to accept log lines on
+    pub socket: Option<PathBuf>,
+    /// Address to receive syslog messages on
+    pub syslog: Option<String>,
+    /// Existing log to replay in simulated real time
+    pub replay: Option<PathBuf>,
+    /// Replay speed multiplier
+    pub replay_speed: f64,
+    /// Number of IPs shown
+    pub top_n: usize,
+    /// Whether query strings are kept in path templates
+    pub keep_query: bool,
+    /// Whether paths are counted as logged, without normalization
+    pub raw_paths: bool,
+    /// File with user-defined path rewrite rules
+    pub path_rules: Option<PathBuf>,
+    /// Query parameters and values tracked, overall and per IP
+    pub query_limits: QueryLimits,
+    /// Proxy networks whose forwarding headers are trusted
+    pub trusted_proxies: Vec<Cidr>,
+    /// Whether to analyze the files once in batch mode instead of tailing them
+    pub analyze: bool,
+    /// Worker threads for batch mode (0 = one per core)
+    pub threads: usize,
+    /// Whether usage help was requested
+    pub show_help: bool,
+}
+
+impl Config {
+    /// Parse configuration from command-line arguments (excluding the program name)
+    pub fn from_args<I>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut paths = Vec::new();
+        let mut start = None;
+        let mut include_rotated = false;
+        let mut read_stdin = false;
+        let mut watch_dir = None;
+        let mut watch_pattern = DEFAULT_WATCH_PATTERN.to_string();
+        let mut idle_timeout = DEFAULT_IDLE_TIMEOUT;
+        let mut format = None;
+        let mut envelope = Envelope::default();
+        let mut state_file = None;
+        let mut refresh_interval = DEFAULT_REFRESH_INTERVAL;
+        let mut force_poll = false;
+        let mut fifo = None;
+        let mut socket = None;
+        let mut syslog = None;
+        let mut replay = None;
+        let mut replay_speed = 1.0;
+        let mut top_n = DEFAULT_TOP_N;
+        let mut keep_query = false;
+        let mut raw_paths = false;
+        let mut path_rules = None;
+        let mut query_limits = QueryLimits::default();
+        let mut trusted_proxies = Vec::new();
+        let mut analyze = false;
+        let mut threads = 0;
+        let mut show_help = false;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => show_help = true,
+                "-" => read_stdin = true,
+                "--rotated" => include_rotated = true,
+                "--poll" => force_poll = true,
+                "--analyze" => analyze = true,
+                "--keep-query" => keep_query = true,
+                "--raw-paths" => raw_paths = true,
+                "--path-rules" => {
+                    path_rules = Some(PathBuf::from(required_value(&arg, args.next())?));
+                }
+                "--threads" => threads = parse_number(&arg, args.next())? as usize,
+                "--fifo" => fifo = Some(PathBuf::from(required_value(&arg, args.next())?)),
+                "--socket" => socket = Some(PathBuf::from(required_value(&arg, args.next())?)),
+                "--syslog" => syslog = Some(required_value(&arg, args.next())?),
+                "--replay" => replay = Some(PathBuf::from(required_value(&arg, args.next())?)),
+                "--speed" => {
+                    let value = required_value(&arg, args.next())?;
+                    replay_speed = value
+                        .trim_end_matches('x')
+                        .parse()
+                        .context(format!("Invalid speed for --speed: {}", value))?;
+                    if !(replay_speed > 0.0 && f64::is_finite(replay_speed)) {
+                        bail!("--speed must be a positive number");
+                    }
+                }
+                "--top" => {
+                    top_n = parse_number(&arg, args.next())? as usize;
+                    if !(1..=100).contains(&top_n) {
+                        bail!("--top must be between 1 and 100");
+                    }
+                }
+                "--query-limit" => query_limits.global = parse_limit(&arg, args.next())?,
+                "--query-ip-limit" => query_limits.per_ip = parse_limit(&arg, args.next())?,
+                "--trusted-proxy" => {
+                    for cidr in required_value(&arg, args.next())?.split(',') {
+                        trusted_proxies.push(Cidr::parse(cidr.trim())?);
+                    }
+                }
+                "--watch-dir" => {
+                    watch_dir = Some(PathBuf::from(required_value(&arg, args.next())?));
+                }
+                "--pattern" => watch_pattern = required_value(&arg, args.next())?,
+                "--idle-timeout" => {
+                    idle_timeout = Duration::from_secs(parse_number(&arg, args.next())?);
+                }
+                "--format" => {
+                    let value = required_value(&arg, args.next())?;
+                    format = match value.as_str() {
+                        "auto" => None,
+                        name => Some(format_by_name(name)?.name().to_string()),
+                    };
+                }
+                "--envelope" => {
+                    envelope = Envelope::from_name(&required_value(&arg, args.next())?)?;
+                }
+                "--state-file" => {
+                    state_file = Some(PathBuf::from(required_value(&arg, args.next())?));
+                }
+                "--refresh-ms" => {
+                    refresh_interval = Duration::from_millis(parse_number(&arg, args.next())?);
+                }
+                "--from-start" => set_start(&mut start, StartPosition::Beginning)?,
+                "--last-lines" => {
+                    let n = parse_number(&arg, args.next())?;
+                    set_start(&mut start, StartPosition::LastLines(n as usize))?;
+                }
+                "--last-bytes" => {
+                    let n = parse_number(&arg, args.next())?;
+                    set_start(&mut start, StartPosition::LastBytes(n))?;
+                }
+                "--since" => {
+                    let value = required_value(&arg, args.next())?;
+                    let time = DateTime::parse_from_rfc3339(&value)
+                        .context(format!("Invalid timestamp for --since: {}", value))?;
+                    set_start(&mut start, StartPosition::Since(time.with_timezone(&Utc)))?;
+                }
+                flag if flag.starts_with('-') => bail!("Unknown option: {}", flag),
+                _ => paths.push(arg),
+            }
+        }
+
+        if raw_paths && (keep_query || path_rules.is_some()) {
+            bail!("--raw-paths cannot be combined with --keep-query or --path-rules");
+        }
+        if include_rotated
+            && matches!(
+                start,
+                Some(StartPosition::LastLines(_) | StartPosition::LastBytes(_))
+            )
+        {
+            bail!("--rotated cannot be combined with --last-lines or --last-bytes");
+        }
+        if analyze && (start.is_some() || include_rotated) {
+            bail!(
+                "--analyze reads whole files and cannot be combined with --from-start, \
+                 --last-lines, --last-bytes, --since or --rotated"
+            );
+        }
+
+        if analyze {
+            let live_options = [
+                ("-", read_stdin),
+                ("--watch-dir", watch_dir.is_some()),
+                ("--fifo", fifo.is_some()),
+                ("--socket", socket.is_some()),
+                ("--syslog", syslog.is_some()),
+                ("--replay", replay.is_some()),
+                ("--state-file", state_file.is_some()),
+            ];
+            if let Some((option, _)) = live_options.iter().find(|(_, given)| *given) {
+                bail!(
+                    "--analyze only reads the given files and cannot be combined with {}",
+                    option
+                );
+            }
+        }
+
+        let has_input = read_stdin
+            || watch_dir.is_some()
+            || fifo.is_some()
+            || socket.is_some()
+            || syslog.is_some()
+            || replay.is_some();
+        if paths.is_empty() && !has_input {
+            paths.push(DEFAULT_LOG_FILE_PATH.to_string());
+        }
+
+        Ok(Config {
+            paths,
+            read_stdin,
+            start: start.unwrap_or_default(),
+            include_rotated,
+            watch_dir,
+            watch_pattern,
+            idle_timeout,
+            format,
+            envelope,
+            state_file,
+            refresh_interval,
+            force_poll,
+            fifo,
+            socket,
+            syslog,
+            replay,
+            replay_speed,
+            top_n,
+            keep_query,
+            raw_paths,
+            path_rules,
+            query_limits,
+            trusted_proxies,
+            analyze,
+            threads,
+            show_help,
+        })
+    }
+}
+
+/// Record the start position, rejecting conflicting start options
+fn set_start(start: &mut Option<StartPosition>, value: StartPosition) -> Result<()> {
+    if start.is_some() {
+        bail!("Only one of --from-start, --last-lines, --last-bytes and --since may be given");
+    }
+    *start = Some(value);
+    Ok(())
+}
+
+/// Get the value following an option, failing if it is missing
+fn required_value(flag: &str, value: Option<String>) -> Result<String> {
+    value.context(format!("Missing value for {}", flag))
+}
+
+/// Parse the numeric value following an option
+fn parse_number(flag: &str, value: Option<String>) -> Result<u64> {
+    let value = required_value(flag, value)?;
+    value
+        .parse()
+        .context(format!("Invalid number for {}: {}", flag, value))
+}
+
+/// Parse the tracking limit following an option, which must be at least 1
+fn parse_limit(flag: &str, value: Option<String>) -> Result<usize> {
+    let limit = parse_number(flag, value)? as usize;
+    if limit == 0 {
+        bail!("{} must be at least 1", flag);
+    }
+    Ok(limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config> {
+        Config::from_args(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_defaults_to_access_log() {
+        let config = parse(&[]).unwrap();
+        assert_eq!(config.paths, vec!["access.log"]);
+        assert!(!config.read_stdin);
+        assert_eq!(config.start, StartPosition::End);
+        assert!(!config.include_rotated);
+        assert_eq!(config.refresh_interval, DEFAULT_REFRESH_INTERVAL);
+        assert!(!config.force_poll);
+        assert!(!config.show_help);
+    }
+
+    #[test]
+    fn test_multiple_paths_and_globs() {
+        let config = parse(&["a/access.log", "--rotated", "logs/*.log"]).unwrap();
+        assert_eq!(config.paths, vec!["a/access.log", "logs/*.log"]);
+        assert!(config.include_rotated);
+    }
+
+    #[test]
+    fn test_stdin_input() {
+        let config = parse(&["-"]).unwrap();
+        assert!(config.read_stdin);
+        assert!(config.paths.is_empty());
+
+        let config = parse(&["-", "access.log"]).unwrap();
+        assert!(config.read_stdin);
+        assert_eq!(config.paths, vec!["access.log"]);
+
+        let config = parse(&["--fifo", "/run/traefik.fifo", "--socket", "/run/logs.sock"]).unwrap();
+        assert_eq!(config.fifo, Some(PathBuf::from("/run/traefik.fifo")));
+        assert_eq!(config.socket, Some(PathBuf::from("/run/logs.sock")));
+        assert!(config.paths.is_empty());
+
+        let config = parse(&["--syslog", "0.0.0.0:514"]).unwrap();
+        assert_eq!(config.syslog.as_deref(), Some("0.0.0.0:514"));
+        assert!(config.paths.is_empty());
+    }
+
+    #[test]
+    fn test_refresh_and_poll_options() {
+        let config = parse(&["--refresh-ms", "250", "--poll"]).unwrap();
+        assert_eq!(config.refresh_interval, Duration::from_millis(250));
+        assert!(config.force_poll);
+
+        let config = parse(&["--state-file", "tailer.json"]).unwrap();
+        assert_eq!(config.state_file, Some(PathBuf::from("tailer.json")));
+
+        let config = parse(&["--format", "clf"]).unwrap();
+        assert_eq!(config.format.as_deref(), Some("clf"));
+        assert_eq!(parse(&["--format", "auto"]).unwrap().format, None);
+        assert!(parse(&["--format", "xml"]).is_err());
+
+        let config = parse(&["--envelope", "cri"]).unwrap();
+        assert_eq!(config.envelope, Envelope::Cri);
+        assert!(parse(&["--envelope", "syslog"]).is_err());
+    }
+
+    #[test]
+    fn test_analyze_options() {
+        let config = parse(&["--analyze", "--threads", "8", "--top", "25", "big.log"]).unwrap();
+        assert!(config.analyze);
+        assert_eq!(config.threads, 8);
+        assert_eq!(config.top_n, 25);
+        assert_eq!(config.paths, vec!["big.log"]);
+        // Start options only apply to tailing
+        assert!(parse(&["--analyze", "--since", "2024-01-15T10:00:00Z"]).is_err());
+        assert!(parse(&["--analyze", "--last-lines", "10"]).is_err());
+        assert!(parse(&["--analyze", "--rotated"]).is_err());
+        // Neither do live inputs or the checkpoint
+        for option in [
+            &["-"][..],
+            &["--watch-dir", "logs"],
+            &["--fifo", "access.fifo"],
+            &["--socket", "access.sock"],
+            &["--syslog", "0.0.0.0:514"],
+            &["--replay", "old.log"],
+            &["--state-file", "state.json"],
+        ] {
+            let args: Vec<&str> = ["--analyze", "big.log"]
+                .iter()
+                .chain(option)
+                .copied()
+                .collect();
+            assert!(parse(&args).is_err(), "accepted {:?}", option);
+        }
+
+        assert_eq!(parse(&[]).unwrap().top_n, DEFAULT_TOP_N);
+        assert!(parse(&["--top", "0"]).is_err());
+        assert!(parse(&["--top", "101"]).is_err());
+    }
+
+    #[test]
+    fn test_replay_options() {
+        let config = parse(&["--replay", "yesterday.log.gz", "--speed", "10x"]).unwrap();
+        assert_eq!(config.replay, Some(PathBuf::from("yesterday.log.gz")));
+        assert_eq!(config.replay_speed, 10.0);
+        assert!(config.paths.is_empty());
+
+        assert_eq!(parse(&[]).unwrap().replay_speed, 1.0);
+        assert_eq!(parse(&["--speed", "0.5"]).unwrap().replay_speed, 0.5);
+        assert!(parse(&["--speed", "0"]).is_err());
+        assert!(parse(&["--speed", "fast"]).is_err());
+    }
+
+    #[test]
+    fn test_path_options() {
+        let config = parse(&["--keep-query", "--path-rules", "routes.json"]).unwrap();
+        assert!(config.keep_query);
+        assert!(!config.raw_paths);
+        assert_eq!(config.path_rules, Some(PathBuf::from("routes.json")));
+
+        assert!(parse(&["--raw-paths"]).unwrap().raw_paths);
+        assert!(parse(&["--raw-paths", "--keep-query"]).is_err());
+    }
+
+    #[test]
+    fn test_query_limit_options() {
+        assert_eq!(parse(&[]).unwrap().query_limits, QueryLimits::default());
+
+        let config = parse(&["--query-limit", "5000", "--query-ip-limit", "20"]).unwrap();
+        assert_eq!(
+            config.query_limits,
+            QueryLimits {
+                global: 5000,
+                per_ip: 20
+            }
+        );
+        assert!(parse(&["--query-ip-limit", "0"]).is_err());
+        assert!(parse(&["--query-limit"]).is_err());
+    }
+
+    #[test]
+    fn test_trusted_proxy_options() {
+        let config = parse(&[
+            "--trusted-proxy",
+            "10.0.0.0/8, 172.16.0.0/12",
+            "--trusted-proxy",
+            "fd00::/8",
+        ])
+        .unwrap();
+        let cidrs: Vec<String> = config
+            .trusted_proxies
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        assert_eq!(cidrs, vec!["10.0.0.0/8", "172.16.0.0/12", "fd00::/8"]);
+
+        assert!(parse(&[]).unwrap().trusted_proxies.is_empty());
+        assert!(parse(&["--trusted-proxy", "10.0.0.0/40"]).is_err());
+    }
+
+    #[test]
+    fn test_watch_dir_options() {
+        let config = parse(&["--watch-dir", "/var/log/traefik"]).unwrap();
+        assert_eq!(config.watch_dir, Some(PathBuf::from("/var/log/traefik")));
+        assert_eq!(config.watch_pattern, DEFAULT_WATCH_PATTERN);
+        assert_eq!(config.idle_timeout, DEFAULT_IDLE_TIMEOUT);
+        assert!(config.paths.is_empty());
+
+        let config = parse(&[
+            "--watch-dir",
+            "logs",
+            "--pattern",
+            "access-*.json",
+            "--idle-timeout",
+            "60",
+        ])
+        .unwrap();
+        assert_eq!(config.watch_pattern, "access-*.json");
+        assert_eq!(config.idle_timeout, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_unknown_option_is_rejected() {
+        assert!(parse(&["--bogus"]).is_err());
+    }
+
+    #[test]
+    fn test_start_positions() {
+        assert_eq!(
+            parse(&["--from-start"]).unwrap().start,
+            StartPosition::Beginning
+        );
+        assert_eq!(
+            parse(&["--last-lines", "500"]).unwrap().start,
+            StartPosition::LastLines(500)
+        );
+        assert_eq!(
+            parse(&["--last-bytes", "1048576", "x.log"]).unwrap().start,
+            StartPosition::LastBytes(1048576)
+        );
+
+        let config = parse(&["--since", "2024-01-15T10:00:00+02:00"]).unwrap();
+        let StartPosition::Since(time) = config.start else {
+            panic!("expected --since start position");
+        };
+        assert_eq!(time.to_rfc3339(), "2024-01-15T08:00:00+00:00");
+
+        assert!(parse(&["--last-lines"]).is_err());
+        assert!(parse(&["--last-lines", "abc"]).is_err());
+        assert!(parse(&["--since", "yesterday"]).is_err());
+        assert!(parse(&["--from-start", "--last-lines", "10"]).is_err());
+        assert!(parse(&["--rotated", "--last-lines", "10"]).is_err());
+        assert!(parse(&["--rotated", "--since", "2024-01-15T10:00:00Z"]).is_ok());
+    }
+}
@@ -0,0 +1,282 @@
+use anyhow::{Context, Result};
+use glob::Pattern;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::file_reader::{LineBuffer, LogTailer, StartPosition, TailerState};
+
+/// New lines read from one file of a watched directory
+/// Borrows the file's line buffer, which the next read refills
+pub struct SourceLines<'a> {
+    pub source: String,
+    pub lines: &'a [String],
+    pub invalid_utf8: usize,
+}
+
+/// A file being tailed by the directory watcher
+struct WatchedFile {
+    tailer: LogTailer,
+    lines: LineBuffer,
+    invalid_utf8: usize,
+    last_activity: Instant,
+}
+
+/// Tails every file in a directory whose name matches a glob pattern
+/// New files (e.g. a fresh per-day log) are picked up on the next scan and read from
+/// their beginning. Files that are deleted or stay idle for `idle_timeout` are no longer
+/// tailed; an idle file that grows again is resumed where it was left off.
+pub struct DirectoryWatcher {
+    dir: PathBuf,
+    pattern: Pattern,
+    idle_timeout: Duration,
+    files: HashMap<PathBuf, WatchedFile>,
+    retired: HashMap<PathBuf, TailerState>,
+}
+
+impl DirectoryWatcher {
+    /// Start watching `dir` for files whose name matches `pattern`
+    /// Files already present are opened at `start`, or at their saved state if
+    /// `saved` knows them
+    pub fn new<F>(
+        dir: &Path,
+        pattern: &str,
+        idle_timeout: Duration,
+        start: StartPosition,
+        saved: F,
+    ) -> Result<Self>
+    where
+        F: Fn(&Path) -> Option<TailerState>,
+    {
+        let pattern =
+            Pattern::new(pattern).context(format!("Invalid file pattern: {}", pattern))?;
+        let mut watcher = DirectoryWatcher {
+            dir: dir.to_path_buf(),
+            pattern,
+            idle_timeout,
+            files: HashMap::new(),
+            retired: HashMap::new(),
+        };
+
+        for path in watcher.matching_files()? {
+            let tailer = LogTailer::resume(&path, saved(&path), start)
+                .context(format!("Failed to open log file: {}", path.display()))?;
+            watcher.track(path, tailer);
+        }
+
+        Ok(watcher)
+    }
+
+    /// Directory being watched
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Glob pattern file names must match
+    pub fn pattern(&self) -> &Pattern {
+        &self.pattern
+    }
+
+    /// Files currently being tailed
+    pub fn tailers(&self) -> impl Iterator<Item = &LogTailer> {
+        self.files.values().map(|file| &file.tailer)
+    }
+
+    /// Pick up new files, read new lines from every tailed file, and stop tailing
+    /// files that were deleted or have gone idle
+    pub fn read_new_lines(&mut self) -> Result<Vec<SourceLines<'_>>> {
+        self.discover_files()?;
+
+        let mut finished = Vec::new();
+
+        for (path, file) in self.files.iter_mut() {
+            file.lines.clear();
+            let read = file.tailer.read_new_lines(&mut file.lines);
+            file.invalid_utf8 = file.tailer.take_invalid_utf8();
+            if let Err(e) = read {
+                eprintln!("Error reading log file {}: {}", path.display(), e);
+                continue;
+            }
+
+            if file.lines.is_empty() {
+                let deleted = !path.exists();
+                if deleted || file.last_activity.elapsed() >= self.idle_timeout {
+                    finished.push((path.clone(), deleted));
+                }
+            } else {
+                file.last_activity = Instant::now();
+            }
+        }
+
+        // Remember where idle files were left off, forget deleted ones
+        for (path, deleted) in finished {
+            if let Some(file) = self.files.remove(&path)
+                && !deleted
+                && let Some(state) = file.tailer.state()
+            {
+                self.retired.insert(path, state);
+            }
+        }
+
+        let batches = self
+            .files
+            .iter()
+            .filter(|(_, file)| !file.lines.is_empty() || file.invalid_utf8 > 0)
+            .map(|(path, file)| SourceLines {
+                source: path.display().to_string(),
+                lines: file.lines.lines(),
+                invalid_utf8: file.invalid_utf8,
+            })
+            .collect();
+        Ok(batches)
+    }
+
+    /// Start tailing matching files that are not tailed yet
+    fn discover_files(&mut self) -> Result<()> {
+        let matching = self.matching_files()?;
+
+        // Retired files that have since been deleted are forgotten
+        self.retired.retain(|path, _| matching.contains(path));
+
+        for path in matching {
+            if self.files.contains_key(&path) {
+                continue;
+            }
+
+            let saved = self.retired.get(&path).copied();
+            if let Some(state) = saved {
+                // Idle file: only reopen once it has changed size
+                let size = fs::metadata(&path).map(|meta| meta.len()).unwrap_or(0);
+                if size == state.position {
+                    continue;
+                }
+            }
+
+            // Files appearing after startup are read from their beginning
+            match LogTailer::resume(&path, saved, StartPosition::Beginning) {
+                Ok(tailer) => {
+                    self.retired.remove(&path);
+                    self.track(path, tailer);
+                }
+                Err(e) => eprintln!("Warning: Failed to open {}: {:#}", path.display(), e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Files in the directory whose name matches the pattern
+    fn matching_files(&self) -> Result<Vec<PathBuf>> {
+        let entries = fs::read_dir(&self.dir)
+            .context(format!("Failed to list directory: {}", self.dir.display()))?;
+
+        let mut paths = Vec::new();
+        for entry in entries {
+            let entry = entry.context("Failed to read directory entry")?;
+            let name = entry.file_name();
+            let is_match = name.to_str().is_some_and(|name| self.pattern.matches(name));
+            if is_match && entry.file_type().is_ok_and(|kind| kind.is_file()) {
+                paths.push(entry.path());
+            }
+        }
+        paths.sort();
+
+        Ok(paths)
+    }
+
+    /// Start tailing a file
+    fn track(&mut self, path: PathBuf, tailer: LogTailer) {
+        let file = WatchedFile {
+            tailer,
+            lines: LineBuffer::default(),
+            invalid_utf8: 0,
+            last_activity: Instant::now(),
+        };
+        self.files.insert(path, file);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn append(path: &Path, line: &str) {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        writeln!(file, "{}", line).unwrap();
+    }
+
+    fn all_lines(batches: Vec<SourceLines>) -> Vec<String> {
+        let mut lines: Vec<String> = batches.iter().flat_map(|b| b.lines.to_vec()).collect();
+        lines.sort();
+        lines
+    }
+
+    #[test]
+    fn test_directory_watcher_discovers_new_files() {
+        let dir = PathBuf::from("test_dir_watcher_discover");
+        fs::create_dir_all(&dir).unwrap();
+        append(&dir.join("existing.log"), "old line");
+
+        let mut watcher = DirectoryWatcher::new(
+            &dir,
+            "*.log",
+            Duration::from_secs(60),
+            StartPosition::End,
+            |_| None,
+        )
+        .unwrap();
+        assert!(watcher.read_new_lines().unwrap().is_empty());
+
+        // New matching file is read from its beginning, other names are ignored
+        append(&dir.join("existing.log"), "existing new line");
+        append(&dir.join("2024-01-02.log"), "new file line");
+        append(&dir.join("notes.txt"), "ignored");
+
+        let lines = all_lines(watcher.read_new_lines().unwrap());
+        assert_eq!(lines, vec!["existing new line", "new file line"]);
+        assert_eq!(watcher.tailers().count(), 2);
+
+        // Deleted files are no longer tailed
+        fs::remove_file(dir.join("2024-01-02.log")).unwrap();
+        assert!(watcher.read_new_lines().unwrap().is_empty());
+        assert_eq!(watcher.tailers().count(), 1);
+
+        // Clean up
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_directory_watcher_retires_and_resumes_idle_files() {
+        let dir = PathBuf::from("test_dir_watcher_idle");
+        fs::create_dir_all(&dir).unwrap();
+        append(&dir.join("a.log"), "first");
+
+        let mut watcher = DirectoryWatcher::new(
+            &dir,
+            "*.log",
+            Duration::ZERO,
+            StartPosition::Beginning,
+            |_| None,
+        )
+        .unwrap();
+        assert_eq!(all_lines(watcher.read_new_lines().unwrap()), vec!["first"]);
+
+        // No new data with a zero idle timeout: the file is retired
+        assert!(watcher.read_new_lines().unwrap().is_empty());
+        assert_eq!(watcher.tailers().count(), 0);
+
+        // It stays retired while unchanged, and resumes without re-reading once it grows
+        assert!(watcher.read_new_lines().unwrap().is_empty());
+        append(&dir.join("a.log"), "second");
+        assert_eq!(all_lines(watcher.read_new_lines().unwrap()), vec!["second"]);
+
+        // Clean up
+        fs::remove_dir_all(&dir).ok();
+    }
+}
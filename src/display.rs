@@ -1,3 +1,5 @@
+use crate::client_ip::IpSource;
+use crate::query_stats::QueryStats;
 use crate::statistics::StatsCollector;
 
 /// Display formatter for console output
@@ -13,6 +15,15 @@ impl DisplayFormatter {
 
     /// Render the statistics to a formatted string
     pub fn render_stats(stats: &StatsCollector, top_n: usize) -> String {
+        Self::render(stats, top_n, true)
+    }
+
+    /// Render the statistics of a one-off analysis, without the live monitor's hints
+    pub fn render_report(stats: &StatsCollector, top_n: usize) -> String {
+        Self::render(stats, top_n, false)
+    }
+
+    fn render(stats: &StatsCollector, top_n: usize, live: bool) -> String {
         let mut output = String::new();
 
         // Header
@@ -30,19 +41,70 @@ impl DisplayFormatter {
             Self::format_number(stats.total_requests()),
             Self::format_number(stats.unique_ips())
         ));
-        output.push_str(&format!(
-            "Showing top {} IPs | Type a number and press Enter to change\n\n",
-            top_n
-        ));
+        let errors = stats.errors();
+        if errors.total() > 0 {
+            output.push_str(&format!(
+                "Problem Lines: {} malformed | {} invalid UTF-8 (decoded lossily) | {} without a client IP\n",
+                Self::format_number(errors.malformed),
+                Self::format_number(errors.invalid_utf8),
+                Self::format_number(errors.no_client_ip)
+            ));
+        }
+        // How client IPs were resolved, once forwarding headers were used
+        let ip_sources = stats.get_ip_sources();
+        if ip_sources
+            .iter()
+            .any(|(source, _)| *source != IpSource::Peer)
+        {
+            let methods: Vec<String> = ip_sources
+                .iter()
+                .map(|(source, count)| format!("{} via {}", Self::format_number(*count), source))
+                .collect();
+            output.push_str(&format!("Client IPs: {}\n", methods.join(" | ")));
+        }
+        if live {
+            output.push_str(&format!(
+                "Showing top {} IPs | Type a number and press Enter to change\n\n",
+                top_n
+            ));
+        } else {
+            output.push_str(&format!("Showing top {} IPs\n\n", top_n));
+        }
 
         // Check if we have any data
+        if stats.total_requests() == 0 && !live {
+            output.push_str("No requests found.\n");
+            return output;
+        }
         if stats.total_requests() == 0 {
             output.push_str("⏳ Waiting for log entries...\n");
-            output.push_str("\nMonitoring access.log for new requests.\n");
+            output.push_str("\nMonitoring log files for new requests.\n");
             output.push_str("Press Ctrl+C to exit.\n");
             return output;
         }
 
+        // Per-source breakdown when tailing more than one log
+        let sources = stats.get_sources();
+        if sources.len() > 1 {
+            output.push_str("Requests by Source:\n");
+            for (source, count, percentage) in &sources {
+                output.push_str(&format!(
+                    "   • {} - {} ({:.1}%)\n",
+                    Self::truncate_path(source, 45),
+                    Self::format_number(*count),
+                    percentage
+                ));
+            }
+            output.push('\n');
+        }
+
+        // Most common query parameters across all requests
+        if !stats.query().is_empty() {
+            output.push_str("Top Query Parameters:\n");
+            Self::render_params(&mut output, stats.query(), 5);
+            output.push('\n');
+        }
+
         // Get top N IPs
         let top_ips = stats.get_top_ips(top_n);
 
@@ -61,6 +123,15 @@ impl DisplayFormatter {
                 percentage
             ));
 
+            // Time range the requests were made in
+            if let (Some(first), Some(last)) = (ip_stats.first_seen, ip_stats.last_seen) {
+                output.push_str(&format!(
+                    "   Seen: {} - {} UTC\n",
+                    first.format("%Y-%m-%d %H:%M:%S"),
+                    last.format("%Y-%m-%d %H:%M:%S")
+                ));
+            }
+
             // Top paths
             let top_paths = ip_stats.top_paths(3);
             if !top_paths.is_empty() {
@@ -75,11 +146,19 @@ impl DisplayFormatter {
                 }
             }
 
+            // Top query parameters
+            if !ip_stats.query.is_empty() {
+                output.push_str("   Top Params:\n");
+                Self::render_params(&mut output, &ip_stats.query, 3);
+            }
+
             output.push('\n');
         }
 
         output.push_str("────────────────────────────────────────────────────────────────\n");
-        output.push_str("Press Ctrl+C to exit.\n");
+        if live {
+            output.push_str("Press Ctrl+C to exit.\n");
+        }
 
         output
     }
@@ -100,11 +179,45 @@ impl DisplayFormatter {
     }
 
     /// Truncate a path to a maximum length, adding "..." if truncated
+    /// Counts characters, since decoded paths and query values need not be ASCII
     fn truncate_path(path: &str, max_len: usize) -> String {
-        if path.len() <= max_len {
+        if path.chars().count() <= max_len {
             path.to_string()
         } else {
-            format!("{}...", &path[..max_len - 3])
+            let kept: String = path.chars().take(max_len - 3).collect();
+            format!("{}...", kept)
+        }
+    }
+
+    /// Render the top query parameters, each with its distinct and most common values
+    /// Counts that may fall short are marked "+", estimated distinct counts "~"
+    fn render_params(output: &mut String, query: &QueryStats, n: usize) {
+        for (name, param) in query.top_params(n) {
+            let values: Vec<String> = param
+                .top_values(3)
+                .iter()
+                .map(|(value, count)| {
+                    format!(
+                        "{} ({}{})",
+                        Self::truncate_path(value, 20),
+                        Self::format_number(*count),
+                        if param.saturated { "+" } else { "" }
+                    )
+                })
+                .collect();
+            output.push_str(&format!(
+                "   • {} - {}{} sent, {}{} distinct values | top: {}\n",
+                Self::truncate_path(name, 20),
+                Self::format_number(param.count),
+                if query.saturated { "+" } else { "" },
+                if param.distinct.is_estimate() {
+                    "~"
+                } else {
+                    ""
+                },
+                Self::format_number(param.distinct_values()),
+                values.join(", ")
+            ));
         }
     }
 
@@ -138,6 +251,10 @@ mod tests {
             DisplayFormatter::truncate_path("/very/long/path/that/exceeds/maximum", 20),
             "/very/long/path/t..."
         );
+        assert_eq!(
+            DisplayFormatter::truncate_path("/café/crème", 8),
+            "/café..."
+        );
     }
 
     #[test]
@@ -146,5 +263,112 @@ mod tests {
         let output = DisplayFormatter::render_stats(&stats, 10);
         assert!(output.contains("Waiting for log entries"));
         assert!(output.contains("Total Requests: 0"));
+        assert!(!output.contains("Problem Lines"));
+    }
+
+    #[test]
+    fn test_render_report_omits_live_hints() {
+        let stats = StatsCollector::new();
+        let output = DisplayFormatter::render_report(&stats, 10);
+        assert!(output.contains("No requests found"));
+        assert!(!output.contains("Ctrl+C"));
+        assert!(!output.contains("Type a number"));
+    }
+
+    #[test]
+    fn test_render_error_classes() {
+        let mut stats = StatsCollector::new();
+        stats.record_malformed();
+        stats.record_invalid_utf8(2);
+        stats.add_entry(&crate::log_entry::TraefikLogEntry::default());
+
+        let output = DisplayFormatter::render_stats(&stats, 10);
+        assert!(output.contains("Problem Lines: 1 malformed | 2 invalid UTF-8"));
+        assert!(output.contains("| 1 without a client IP"));
+    }
+
+    #[test]
+    fn test_render_per_source_breakdown() {
+        use crate::log_entry::TraefikLogEntry;
+
+        let mut stats = StatsCollector::new();
+        for source in ["a/access.log", "b/access.log", "b/access.log"] {
+            stats.add_entry(&TraefikLogEntry {
+                client_host: Some("10.0.0.1".to_string()),
+                source: Some(source.to_string()),
+                ..Default::default()
+            });
+        }
+
+        let output = DisplayFormatter::render_stats(&stats, 10);
+        assert!(output.contains("Requests by Source:"));
+        assert!(output.contains("b/access.log - 2 (66.7%)"));
+        assert!(output.contains("a/access.log - 1 (33.3%)"));
+    }
+
+    #[test]
+    fn test_render_seen_range() {
+        use crate::log_entry::TraefikLogEntry;
+
+        let mut stats = StatsCollector::new();
+        for time in ["2024-01-15T10:30:00Z", "2024-01-15T09:00:00+01:00"] {
+            let entry = TraefikLogEntry {
+                client_host: Some("10.0.0.1".to_string()),
+                start_utc: Some(chrono::DateTime::parse_from_rfc3339(time).unwrap().to_utc()),
+                ..Default::default()
+            };
+            stats.add_entry(&entry.with_timestamp());
+        }
+
+        let output = DisplayFormatter::render_stats(&stats, 10);
+        assert!(output.contains("Seen: 2024-01-15 08:00:00 - 2024-01-15 10:30:00 UTC"));
+    }
+
+    #[test]
+    fn test_render_query_parameters() {
+        use crate::log_entry::TraefikLogEntry;
+
+        let mut stats = StatsCollector::new();
+        for path in [
+            "/search?q=shoes&_=1",
+            "/search?q=shoes&_=2",
+            "/search?q=hats",
+        ] {
+            stats.add_entry(&TraefikLogEntry {
+                client_host: Some("10.0.0.1".to_string()),
+                request_path: Some(path.to_string()),
+                ..Default::default()
+            });
+        }
+
+        let output = DisplayFormatter::render_stats(&stats, 10);
+        assert!(output.contains("Top Query Parameters:"));
+        assert!(output.contains("   • q - 3 sent, 2 distinct values | top: shoes (2), hats (1)"));
+        assert!(output.contains("   • _ - 2 sent, 2 distinct values | top: 1 (1), 2 (1)"));
+        assert!(output.contains("   Top Params:"));
+    }
+
+    #[test]
+    fn test_render_report_marks_approximate_query_counts() {
+        use crate::log_entry::TraefikLogEntry;
+        use crate::query_stats::QueryLimits;
+
+        let limits = QueryLimits {
+            global: 2,
+            per_ip: 2,
+        };
+        let mut stats = StatsCollector::new().with_query_limits(limits);
+        for i in 0..500 {
+            stats.add_entry(&TraefikLogEntry {
+                client_host: Some("10.0.0.1".to_string()),
+                request_path: Some(format!("/search?q=hats&_={}", i)),
+                ..Default::default()
+            });
+        }
+
+        let output = DisplayFormatter::render_report(&stats, 10);
+        assert!(output.contains("   • q - 500 sent, 1 distinct values | top: hats (500)"));
+        assert!(output.contains("   • _ - 500 sent, ~"));
+        assert!(output.contains("distinct values | top: 498 (1+), 499 (1+)"));
     }
 }
@@ -0,0 +1,223 @@
+use anyhow::{Result, bail};
+use chrono::DateTime;
+use serde::Deserialize;
+use std::borrow::Cow;
+
+/// Container log envelope wrapped around each Traefik line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Envelope {
+    /// Detect the envelope of every line (default)
+    #[default]
+    Auto,
+    /// Lines are Traefik output as-is
+    None,
+    /// Docker json-file driver: `{"log":"{...}\n","stream":"stdout","time":"..."}`
+    Docker,
+    /// CRI (containerd, CRI-O, `/var/log/pods`): `2024-01-01T00:00:00Z stdout F {...}`
+    Cri,
+}
+
+impl Envelope {
+    /// Parse an envelope name as given on the command line
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "auto" => Ok(Envelope::Auto),
+            "none" => Ok(Envelope::None),
+            "docker" => Ok(Envelope::Docker),
+            "cri" => Ok(Envelope::Cri),
+            _ => bail!(
+                "Unknown envelope: {} (expected auto, none, docker or cri)",
+                name
+            ),
+        }
+    }
+
+    /// Detect the envelope of a single line
+    pub fn detect(line: &str) -> Self {
+        if line.starts_with("{\"log\":") {
+            Envelope::Docker
+        } else if parse_cri(line).is_some() {
+            Envelope::Cri
+        } else {
+            Envelope::None
+        }
+    }
+}
+
+impl Envelope {
+    /// Whether a raw line is a split record that the next line continues
+    pub fn is_partial(self, line: &str) -> bool {
+        match self {
+            Envelope::Auto => match Envelope::detect(line) {
+                Envelope::Auto | Envelope::None => false,
+                envelope => envelope.is_partial(line),
+            },
+            Envelope::None => false,
+            Envelope::Docker => serde_json::from_str::<DockerLine>(line)
+                .is_ok_and(|record| !record.log.ends_with('\n')),
+            Envelope::Cri => parse_cri(line).is_some_and(|(partial, _)| partial),
+        }
+    }
+}
+
+/// One line of a Docker json-file log
+#[derive(Deserialize)]
+struct DockerLine {
+    log: String,
+}
+
+/// Split a CRI log line into its partial flag and content
+/// Format: `<RFC 3339 time> <stdout|stderr> <F|P> <content>`
+fn parse_cri(line: &str) -> Option<(bool, &str)> {
+    let mut parts = line.splitn(4, ' ');
+    let time = parts.next()?;
+    let stream = parts.next()?;
+    let tag = parts.next()?;
+    let content = parts.next().unwrap_or("");
+
+    if stream != "stdout" && stream != "stderr" {
+        return None;
+    }
+    let partial = match tag {
+        "F" => false,
+        "P" => true,
+        _ => return None,
+    };
+    DateTime::parse_from_rfc3339(time).ok()?;
+
+    Some((partial, content))
+}
+
+/// Stateful decoder that unwraps container log envelopes for one input
+/// Both runtimes split long lines into several records (Docker omits the trailing
+/// newline, CRI tags them `P`), so partial records are joined before being returned
+#[derive(Debug, Default)]
+pub struct EnvelopeDecoder {
+    envelope: Envelope,
+    partial: String,
+}
+
+impl EnvelopeDecoder {
+    /// Create a decoder for the given envelope
+    pub fn new(envelope: Envelope) -> Self {
+        EnvelopeDecoder {
+            envelope,
+            partial: String::new(),
+        }
+    }
+
+    /// Unwrap one line
+    /// Returns the Traefik line once it is complete, or None while a split line is
+    /// still being joined. Lines that don't match the expected envelope are returned
+    /// unchanged, so they surface as parse errors further down the pipeline.
+    /// Lines without an envelope are borrowed rather than copied.
+    pub fn decode<'a>(&mut self, line: &'a str) -> Option<Cow<'a, str>> {
+        let envelope = match self.envelope {
+            Envelope::Auto => Envelope::detect(line),
+            envelope => envelope,
+        };
+
+        let (partial, content) = match envelope {
+            Envelope::Auto | Envelope::None => return Some(Cow::Borrowed(line)),
+            Envelope::Docker => match serde_json::from_str::<DockerLine>(line) {
+                Ok(record) => {
+                    let partial = !record.log.ends_with('\n');
+                    (partial, record.log)
+                }
+                Err(_) => return Some(Cow::Borrowed(line)),
+            },
+            Envelope::Cri => match parse_cri(line) {
+                // A complete record with nothing pending needs no copy
+                Some((false, content)) if self.partial.is_empty() => {
+                    return Some(Cow::Borrowed(content.trim()));
+                }
+                Some((partial, content)) => (partial, content.to_string()),
+                None => return Some(Cow::Borrowed(line)),
+            },
+        };
+
+        self.partial.push_str(&content);
+        if partial {
+            return None;
+        }
+
+        let complete = std::mem::take(&mut self.partial);
+        Some(Cow::Owned(complete.trim().to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRAEFIK_LINE: &str = r#"{"ClientHost":"10.0.0.1","RequestPath":"/api"}"#;
+
+    #[test]
+    fn test_detect_envelopes() {
+        assert_eq!(Envelope::detect(TRAEFIK_LINE), Envelope::None);
+        assert_eq!(
+            Envelope::detect(r#"{"log":"x\n","stream":"stdout","time":"2024-01-01T00:00:00Z"}"#),
+            Envelope::Docker
+        );
+        assert_eq!(
+            Envelope::detect("2024-01-01T00:00:00.123456789Z stdout F {}"),
+            Envelope::Cri
+        );
+        assert_eq!(
+            Envelope::detect("10.0.0.1 - - [01/Jan/2024:00:00:00 +0000] \"GET / HTTP/1.1\""),
+            Envelope::None
+        );
+    }
+
+    #[test]
+    fn test_decode_docker_json_file() {
+        let mut decoder = EnvelopeDecoder::new(Envelope::Auto);
+        let line = format!(
+            r#"{{"log":{},"stream":"stdout","time":"2024-01-01T00:00:00.000000001Z"}}"#,
+            serde_json::to_string(&format!("{}\n", TRAEFIK_LINE)).unwrap()
+        );
+        assert_eq!(decoder.decode(&line).as_deref(), Some(TRAEFIK_LINE));
+
+        // A line split in two records, only the last ends with a newline
+        let (head, tail) = TRAEFIK_LINE.split_at(10);
+        let first = format!(
+            r#"{{"log":{},"stream":"stdout","time":"2024-01-01T00:00:00Z"}}"#,
+            serde_json::to_string(head).unwrap()
+        );
+        let second = format!(
+            r#"{{"log":{},"stream":"stdout","time":"2024-01-01T00:00:00Z"}}"#,
+            serde_json::to_string(&format!("{}\n", tail)).unwrap()
+        );
+        assert_eq!(decoder.decode(&first), None);
+        assert_eq!(decoder.decode(&second).as_deref(), Some(TRAEFIK_LINE));
+    }
+
+    #[test]
+    fn test_decode_cri() {
+        let mut decoder = EnvelopeDecoder::new(Envelope::Cri);
+        let line = format!("2024-01-01T00:00:00.123Z stdout F {}", TRAEFIK_LINE);
+        assert_eq!(decoder.decode(&line).as_deref(), Some(TRAEFIK_LINE));
+
+        let (head, tail) = TRAEFIK_LINE.split_at(10);
+        assert_eq!(
+            decoder.decode(&format!("2024-01-01T00:00:00Z stdout P {}", head)),
+            None
+        );
+        assert_eq!(
+            decoder
+                .decode(&format!("2024-01-01T00:00:00Z stdout F {}", tail))
+                .as_deref(),
+            Some(TRAEFIK_LINE)
+        );
+
+        // Unwrapped lines pass through unchanged
+        assert_eq!(decoder.decode(TRAEFIK_LINE).as_deref(), Some(TRAEFIK_LINE));
+    }
+
+    #[test]
+    fn test_envelope_from_name() {
+        assert_eq!(Envelope::from_name("docker").unwrap(), Envelope::Docker);
+        assert_eq!(Envelope::from_name("cri").unwrap(), Envelope::Cri);
+        assert!(Envelope::from_name("journald").is_err());
+    }
+}
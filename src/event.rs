@@ -0,0 +1,21 @@
+/// Events delivered to the monitoring loop over a single channel
+/// Readers, the file watcher and the command thread all send into the same channel,
+/// so the loop sleeps until something happens instead of polling on a fixed timer
+/// The channel is bounded, so senders wait while the loop catches up
+#[derive(Debug, PartialEq)]
+pub enum MonitorEvent {
+    /// One or more tailed files changed on disk and may have new lines
+    FilesChanged,
+    /// Lines read from a streaming input, tagged with their source
+    /// `invalid_utf8` counts the lines that had to be decoded lossily
+    Lines {
+        source: String,
+        lines: Vec<String>,
+        invalid_utf8: usize,
+    },
+    /// A streaming input reached its end (a connection closed or a writer left)
+    /// Its decoding state is dropped; its requests stay in the statistics
+    SourceClosed(String),
+    /// The user asked to display a different number of top IPs
+    TopN(usize),
+}
@@ -1,77 +1,396 @@
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::format::parse_line;
 
 use std::fs::OpenOptions;
 #[cfg(windows)]
 use std::os::windows::fs::OpenOptionsExt;
 
+/// Identity of an opened file (device + inode on Unix, volume + file index on Windows)
+/// Used to detect when the log path has been rotated to a new file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileId {
+    dev: u64,
+    ino: u64,
+}
+
+impl FileId {
+    /// Read the identity of an opened file
+    #[cfg(unix)]
+    pub fn of(file: &File) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt;
+
+        let meta = file.metadata().ok()?;
+        Some(FileId {
+            dev: meta.dev(),
+            ino: meta.ino(),
+        })
+    }
+
+    /// Read the identity of an opened file
+    #[cfg(windows)]
+    pub fn of(file: &File) -> Option<Self> {
+        use std::os::windows::io::AsRawHandle;
+        use winapi::um::fileapi::{BY_HANDLE_FILE_INFORMATION, GetFileInformationByHandle};
+
+        let mut info: BY_HANDLE_FILE_INFORMATION = unsafe { std::mem::zeroed() };
+        let ok = unsafe { GetFileInformationByHandle(file.as_raw_handle() as _, &mut info) };
+        if ok == 0 {
+            return None;
+        }
+
+        Some(FileId {
+            dev: info.dwVolumeSerialNumber as u64,
+            ino: ((info.nFileIndexHigh as u64) << 32) | info.nFileIndexLow as u64,
+        })
+    }
+
+    /// File identity is not available on this platform
+    #[cfg(not(any(unix, windows)))]
+    pub fn of(_file: &File) -> Option<Self> {
+        None
+    }
+}
+
+/// Open a log file for reading
+/// On Windows, opens the file with shared read/write/delete access so Traefik can keep
+/// writing to it and log rotation can rename or delete it while we hold the handle
+fn open_shared(path: &Path) -> std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.read(true);
+
+    #[cfg(windows)]
+    options.share_mode(0x01 | 0x02 | 0x04); // FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE
+
+    options.open(path)
+}
+
+/// Where a LogTailer starts reading when it opens a file
+/// Every position other than `End` backfills existing content before tailing live
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StartPosition {
+    /// Ignore existing content and only read newly appended lines
+    #[default]
+    End,
+    /// Read the whole file from the beginning
+    Beginning,
+    /// Read the last N lines of the file
+    LastLines(usize),
+    /// Read the last N bytes of the file, starting at the next full line
+    LastBytes(u64),
+    /// Read every entry whose StartUTC is at or after the given time
+    Since(DateTime<Utc>),
+    /// Continue from a byte offset, e.g. one saved in a checkpoint
+    Offset(u64),
+}
+
+impl fmt::Display for StartPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StartPosition::End => write!(f, "ignoring existing entries"),
+            StartPosition::Beginning => write!(f, "reading from the beginning"),
+            StartPosition::LastLines(n) => write!(f, "reading the last {} lines", n),
+            StartPosition::LastBytes(n) => write!(f, "reading the last {} bytes", n),
+            StartPosition::Since(time) => {
+                write!(f, "reading entries since {}", time.to_rfc3339())
+            }
+            StartPosition::Offset(offset) => write!(f, "resuming at byte {}", offset),
+        }
+    }
+}
+
+/// Saved progress of a tailer: which file it was reading and how far it got
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TailerState {
+    pub file_id: FileId,
+    pub position: u64,
+}
+
+/// Expand a list of file paths and glob patterns into the files to tail
+/// Plain paths are kept as-is (and must exist when the tailer opens them), glob patterns
+/// are expanded to the matching files. Duplicates are removed, order is preserved.
+pub fn expand_paths(patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+
+    for pattern in patterns {
+        let is_glob = pattern.contains(['*', '?', '[']);
+        if !is_glob {
+            let path = PathBuf::from(pattern);
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+            continue;
+        }
+
+        let mut matched = false;
+        for entry in glob::glob(pattern).context(format!("Invalid glob pattern: {}", pattern))? {
+            let path = entry.context(format!("Failed to expand glob pattern: {}", pattern))?;
+            matched = true;
+            if path.is_file() && !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+
+        if !matched {
+            bail!("No files match pattern: {}", pattern);
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Decode a raw line as UTF-8, replacing invalid sequences with U+FFFD
+/// Returns the decoded line and whether it contained invalid UTF-8. Request paths from
+/// scanners can carry raw bytes, and those lines must not stop the reader.
+pub fn decode_line(bytes: Vec<u8>) -> (String, bool) {
+    match String::from_utf8(bytes) {
+        Ok(line) => (line, false),
+        Err(e) => (String::from_utf8_lossy(e.as_bytes()).into_owned(), true),
+    }
+}
+
+/// Lines kept allocated by a cleared LineBuffer; a larger backfill gives the rest back
+const RETAINED_LINES: usize = 4096;
+
+/// Reusable buffer of trimmed, non-empty lines
+/// Clearing keeps the line strings allocated, so a reader that refills the buffer
+/// again and again copies each line into existing capacity instead of allocating it.
+#[derive(Debug, Default)]
+pub struct LineBuffer {
+    lines: Vec<String>,
+    len: usize,
+}
+
+impl LineBuffer {
+    /// Decode a raw line (lossily, see `decode_line`), trim it, and append it unless empty
+    /// Returns whether the line contained invalid UTF-8
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> bool {
+        let (text, invalid) = match std::str::from_utf8(bytes) {
+            Ok(text) => (Cow::Borrowed(text), false),
+            Err(_) => (String::from_utf8_lossy(bytes), true),
+        };
+        let trimmed = text.trim();
+        if trimmed.is_empty() {
+            return invalid;
+        }
+
+        if self.len == self.lines.len() {
+            self.lines.push(String::new());
+        }
+        let line = &mut self.lines[self.len];
+        line.clear();
+        line.push_str(trimmed);
+        self.len += 1;
+        invalid
+    }
+
+    /// The lines pushed since the last clear
+    pub fn lines(&self) -> &[String] {
+        &self.lines[..self.len]
+    }
+
+    /// Number of lines pushed since the last clear
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether no lines were pushed since the last clear
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Forget the lines, keeping their allocations for the next fill
+    pub fn clear(&mut self) {
+        if self.lines.len() > RETAINED_LINES {
+            self.lines.truncate(RETAINED_LINES);
+            self.lines.shrink_to_fit();
+        }
+        self.len = 0;
+    }
+}
+
 /// Tailer for reading new lines appended to a log file
+/// Follows the path across log rotation (rename and recreate) and truncation in place
 pub struct LogTailer {
+    path: PathBuf,
     reader: BufReader<File>,
+    file_id: Option<FileId>,
     position: u64,
+    invalid_utf8: usize,
+    // Raw bytes of the line being read, reused across lines
+    bytes: Vec<u8>,
 }
 
 impl LogTailer {
     /// Create a new LogTailer for the specified file path
     /// On Windows, opens the file with shared read/write access to avoid blocking Traefik
     /// Initially seeks to the end of the file to ignore existing entries
-    pub fn new(path: &str) -> Result<Self> {
-        // Open file with shared read/write access on Windows
-        #[cfg(windows)]
-        let file = OpenOptions::new()
-            .read(true)
-            .share_mode(0x01 | 0x02) // FILE_SHARE_READ | FILE_SHARE_WRITE
-            .open(path)
-            .context(format!("Failed to open file: {}", path))?;
-
-        // For non-Windows platforms (for testing)
-        #[cfg(not(windows))]
-        let file = OpenOptions::new()
-            .read(true)
-            .open(path)
-            .context(format!("Failed to open file: {}", path))?;
+    #[cfg(test)]
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::with_start(path, StartPosition::End)
+    }
+
+    /// Create a new LogTailer that starts reading at the given position
+    /// The first call to `read_new_lines` reads the backfilled content, later calls
+    /// return live updates as usual
+    pub fn with_start<P: AsRef<Path>>(path: P, start: StartPosition) -> Result<Self> {
+        let path = path.as_ref();
+        let file = open_shared(path).context(format!("Failed to open file: {}", path.display()))?;
+        let file_id = FileId::of(&file);
 
         let mut reader = BufReader::new(file);
 
-        // Seek to end of file to ignore existing entries
-        let position = reader
-            .seek(SeekFrom::End(0))
-            .context("Failed to seek to end of file")?;
+        let position = match start {
+            // Seek to end of file to ignore existing entries
+            StartPosition::End => reader
+                .seek(SeekFrom::End(0))
+                .context("Failed to seek to end of file")?,
+            StartPosition::Beginning => 0,
+            StartPosition::LastLines(n) => {
+                offset_of_last_lines(&mut reader, n).context("Failed to find last lines")?
+            }
+            StartPosition::LastBytes(n) => {
+                offset_of_last_bytes(&mut reader, n).context("Failed to find last bytes")?
+            }
+            StartPosition::Since(time) => {
+                offset_of_time(&mut reader, time).context("Failed to find start time")?
+            }
+            StartPosition::Offset(offset) => offset,
+        };
+
+        Ok(LogTailer {
+            path: path.to_path_buf(),
+            reader,
+            file_id,
+            position,
+            invalid_utf8: 0,
+            bytes: Vec::new(),
+        })
+    }
 
-        Ok(LogTailer { reader, position })
+    /// Create a LogTailer that continues where a previous run left off
+    /// Resumes at the saved offset if the path still refers to the same file, reads the
+    /// new file from the beginning if it was rotated or truncated since, and uses `start`
+    /// when there is no saved state
+    pub fn resume<P: AsRef<Path>>(
+        path: P,
+        saved: Option<TailerState>,
+        start: StartPosition,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let Some(saved) = saved else {
+            return Self::with_start(path, start);
+        };
+
+        let file = open_shared(path).context(format!("Failed to open file: {}", path.display()))?;
+        let size = file
+            .metadata()
+            .context("Failed to read file metadata")?
+            .len();
+
+        let start = if FileId::of(&file) == Some(saved.file_id) && saved.position <= size {
+            StartPosition::Offset(saved.position)
+        } else {
+            StartPosition::Beginning
+        };
+
+        Self::with_start(path, start)
     }
 
-    /// Read new lines that have been appended to the file since the last read
-    /// Returns a vector of new complete lines
-    pub fn read_new_lines(&mut self) -> Result<Vec<String>> {
-        let mut lines = Vec::new();
+    /// Path of the file being tailed
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Current progress, for saving to a checkpoint
+    /// None if the platform cannot identify files
+    pub fn state(&self) -> Option<TailerState> {
+        self.file_id.map(|file_id| TailerState {
+            file_id,
+            position: self.position,
+        })
+    }
 
-        // Check if there's new data
+    /// Read new lines that have been appended to the file since the last read
+    /// Appends the new complete lines to `lines`, reusing its allocations
+    ///
+    /// Only newline-terminated lines are returned. An unterminated tail (Traefik in the
+    /// middle of a write) stays unread and `position` is not advanced past it, so it is
+    /// returned as a whole line once the rest of it has been written.
+    ///
+    /// If the file was truncated in place (e.g. logrotate `copytruncate`), reading restarts
+    /// from offset 0. If the path now refers to a different file (renamed and recreated),
+    /// the old handle is drained first and then the new file is read from offset 0.
+    pub fn read_new_lines(&mut self, lines: &mut LineBuffer) -> Result<()> {
+        // A file shorter than our offset has been truncated in place
         let file_size = self
             .reader
-            .seek(SeekFrom::End(0))
-            .context("Failed to seek to end of file")?;
+            .get_ref()
+            .metadata()
+            .context("Failed to read file metadata")?
+            .len();
+        if file_size < self.position {
+            self.position = 0;
+        }
+
+        // Drain whatever is left in the currently open file
+        self.read_available(lines, false)?;
+
+        // Switch to the new file if the path has been rotated
+        if let Some(file) = self.rotated_file() {
+            // The old file is abandoned, so an unterminated last line will never be
+            // completed: flush it as-is before switching
+            self.read_available(lines, true)?;
 
-        if file_size <= self.position {
-            // No new data, restore position and return empty
-            self.reader
-                .seek(SeekFrom::Start(self.position))
-                .context("Failed to restore file position")?;
-            return Ok(lines);
+            self.file_id = FileId::of(&file);
+            self.reader = BufReader::new(file);
+            self.position = 0;
+            self.read_available(lines, false)?;
         }
 
+        Ok(())
+    }
+
+    /// Number of lines containing invalid UTF-8 since the last call
+    /// Such lines are still read by `read_new_lines`, decoded lossily
+    pub fn take_invalid_utf8(&mut self) -> usize {
+        std::mem::take(&mut self.invalid_utf8)
+    }
+
+    /// Check whether the path now refers to a different file than the one we hold open
+    /// Returns the newly opened file if so. A missing path means the old file was renamed
+    /// but not yet recreated, so we keep reading the old handle.
+    fn rotated_file(&self) -> Option<File> {
+        let current_id = self.file_id?;
+        let file = open_shared(&self.path).ok()?;
+
+        match FileId::of(&file) {
+            Some(id) if id != current_id => Some(file),
+            _ => None,
+        }
+    }
+
+    /// Read all complete lines from the current position to the end of the open file
+    /// With `include_partial`, an unterminated last line is returned too
+    fn read_available(&mut self, lines: &mut LineBuffer, include_partial: bool) -> Result<()> {
         // Seek to our last read position
         self.reader
             .seek(SeekFrom::Start(self.position))
             .context("Failed to seek to last position")?;
 
-        // Read new lines
+        // Read new lines as raw bytes, so invalid UTF-8 never stalls the reader
         loop {
-            let mut line = String::new();
+            self.bytes.clear();
             let bytes_read = self
                 .reader
-                .read_line(&mut line)
+                .read_until(b'\n', &mut self.bytes)
                 .context("Failed to read line from file")?;
 
             if bytes_read == 0 {
@@ -79,26 +398,139 @@ impl LogTailer {
                 break;
             }
 
+            if !self.bytes.ends_with(b"\n") && !include_partial {
+                // Traefik is mid-write: leave the tail for the next read, which sees
+                // it again together with the rest of the line
+                break;
+            }
+
             // Update position
             self.position += bytes_read as u64;
 
-            // Trim whitespace and skip empty lines
-            let trimmed = line.trim();
-            if !trimmed.is_empty() {
-                lines.push(trimmed.to_string());
+            // Trims whitespace and skips empty lines
+            if lines.push_bytes(&self.bytes) {
+                self.invalid_utf8 += 1;
             }
         }
 
-        Ok(lines)
+        Ok(())
     }
 }
 
+/// Find the offset where the last `n` lines of the file begin
+/// Scans backwards from the end of the file in fixed-size chunks
+fn offset_of_last_lines(reader: &mut BufReader<File>, n: usize) -> std::io::Result<u64> {
+    let size = reader.seek(SeekFrom::End(0))?;
+    if n == 0 {
+        return Ok(size);
+    }
+
+    let mut buf = vec![0u8; 8192];
+    let mut chunk_end = size;
+    let mut newlines = 0;
+
+    while chunk_end > 0 {
+        let chunk_len = chunk_end.min(buf.len() as u64) as usize;
+        let chunk_start = chunk_end - chunk_len as u64;
+        reader.seek(SeekFrom::Start(chunk_start))?;
+        reader.read_exact(&mut buf[..chunk_len])?;
+
+        for (i, byte) in buf[..chunk_len].iter().enumerate().rev() {
+            let line_start = chunk_start + i as u64 + 1;
+            // The newline terminating the final line does not start a new line
+            if *byte != b'\n' || line_start == size {
+                continue;
+            }
+
+            newlines += 1;
+            if newlines == n {
+                return Ok(line_start);
+            }
+        }
+
+        chunk_end = chunk_start;
+    }
+
+    // Fewer than n lines in the file
+    Ok(0)
+}
+
+/// Find the offset of the first full line within the last `n` bytes of the file
+fn offset_of_last_bytes(reader: &mut BufReader<File>, n: u64) -> std::io::Result<u64> {
+    let size = reader.seek(SeekFrom::End(0))?;
+    if n >= size {
+        return Ok(0);
+    }
+
+    // Skip the rest of a line we landed in the middle of
+    let start = size - n;
+    reader.seek(SeekFrom::Start(start - 1))?;
+    let mut partial = Vec::new();
+    let skipped = reader.read_until(b'\n', &mut partial)?;
+
+    Ok(start - 1 + skipped as u64)
+}
+
+/// Find the offset of the first entry whose StartUTC is at or after `since`
+/// Lines that do not parse or have no StartUTC are skipped. Returns the end of the
+/// file if no entry is recent enough.
+fn offset_of_time(reader: &mut BufReader<File>, since: DateTime<Utc>) -> std::io::Result<u64> {
+    reader.seek(SeekFrom::Start(0))?;
+
+    let mut offset = 0;
+    loop {
+        let mut bytes = Vec::new();
+        let bytes_read = reader.read_until(b'\n', &mut bytes)?;
+        if bytes_read == 0 {
+            return Ok(offset);
+        }
+        let (line, _) = decode_line(bytes);
+        if started_since(line.trim(), since) {
+            return Ok(offset);
+        }
+
+        offset += bytes_read as u64;
+    }
+}
+
+/// Whether a line is an entry whose StartUTC is at or after `since`
+/// Lines that do not parse or have no StartUTC never are
+pub fn started_since(line: &str, since: DateTime<Utc>) -> bool {
+    parse_line(line)
+        .ok()
+        .and_then(|entry| entry.start_time())
+        .is_some_and(|start| start >= since)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::fs;
     use std::io::Write;
 
+    /// Read the new lines of a tailer into a fresh buffer
+    fn read(tailer: &mut LogTailer) -> Vec<String> {
+        let mut lines = LineBuffer::default();
+        tailer.read_new_lines(&mut lines).unwrap();
+        lines.lines().to_vec()
+    }
+
+    #[test]
+    fn test_line_buffer_reuses_cleared_lines() {
+        let mut lines = LineBuffer::default();
+        assert!(!lines.push_bytes(b"  first line\n"));
+        assert!(!lines.push_bytes(b" \r\n"));
+        assert!(lines.push_bytes(b"bad \xff byte\n"));
+        assert_eq!(lines.lines(), ["first line", "bad \u{fffd} byte"]);
+
+        let capacity = lines.lines()[0].capacity();
+        lines.clear();
+        assert!(lines.is_empty());
+        lines.push_bytes(b"next");
+        assert_eq!(lines.lines(), ["next"]);
+        assert_eq!(lines.lines()[0].capacity(), capacity);
+    }
+
     #[test]
     fn test_log_tailer_ignores_existing_entries() {
         // Create a temporary test file
@@ -114,7 +546,7 @@ mod tests {
         let mut tailer = LogTailer::new(test_file).unwrap();
 
         // Read should return empty (existing lines ignored)
-        let lines = tailer.read_new_lines().unwrap();
+        let lines = read(&mut tailer);
         assert_eq!(lines.len(), 0);
 
         // Append new content
@@ -124,7 +556,7 @@ mod tests {
         drop(file);
 
         // Read should return only new lines
-        let lines = tailer.read_new_lines().unwrap();
+        let lines = read(&mut tailer);
         assert_eq!(lines.len(), 2);
         assert_eq!(lines[0], "new line 1");
         assert_eq!(lines[1], "new line 2");
@@ -148,7 +580,7 @@ mod tests {
         writeln!(file, "batch 1 line 1").unwrap();
         drop(file);
 
-        let lines = tailer.read_new_lines().unwrap();
+        let lines = read(&mut tailer);
         assert_eq!(lines.len(), 1);
         assert_eq!(lines[0], "batch 1 line 1");
 
@@ -158,16 +590,309 @@ mod tests {
         writeln!(file, "batch 2 line 2").unwrap();
         drop(file);
 
-        let lines = tailer.read_new_lines().unwrap();
+        let lines = read(&mut tailer);
         assert_eq!(lines.len(), 2);
         assert_eq!(lines[0], "batch 2 line 1");
         assert_eq!(lines[1], "batch 2 line 2");
 
         // Read again without new data
-        let lines = tailer.read_new_lines().unwrap();
+        let lines = read(&mut tailer);
         assert_eq!(lines.len(), 0);
 
         // Clean up
         fs::remove_file(test_file).ok();
     }
+
+    #[test]
+    fn test_log_tailer_follows_rename_and_recreate() {
+        let test_file = "test_log_tailer_rotate.log";
+        let rotated_file = "test_log_tailer_rotate.log.1";
+
+        fs::File::create(test_file).unwrap();
+        let mut tailer = LogTailer::new(test_file).unwrap();
+
+        // Line written before rotation
+        let mut file = fs::OpenOptions::new().append(true).open(test_file).unwrap();
+        writeln!(file, "before rotation").unwrap();
+
+        // Rotate: rename the file, then a late write lands in the old file
+        fs::rename(test_file, rotated_file).unwrap();
+        writeln!(file, "late write to old file").unwrap();
+        drop(file);
+
+        // Path is missing until Traefik recreates it, keep reading the old handle
+        let lines = read(&mut tailer);
+        assert_eq!(lines, vec!["before rotation", "late write to old file"]);
+
+        // Recreate the file and write to it
+        let mut file = fs::File::create(test_file).unwrap();
+        writeln!(file, "after rotation").unwrap();
+        drop(file);
+
+        let lines = read(&mut tailer);
+        assert_eq!(lines, vec!["after rotation"]);
+
+        // Subsequent appends to the new file are picked up
+        let mut file = fs::OpenOptions::new().append(true).open(test_file).unwrap();
+        writeln!(file, "second line in new file").unwrap();
+        drop(file);
+
+        let lines = read(&mut tailer);
+        assert_eq!(lines, vec!["second line in new file"]);
+
+        // Clean up
+        fs::remove_file(test_file).ok();
+        fs::remove_file(rotated_file).ok();
+    }
+
+    #[test]
+    fn test_log_tailer_drains_old_file_before_switching() {
+        let test_file = "test_log_tailer_drain.log";
+        let rotated_file = "test_log_tailer_drain.log.1";
+
+        fs::File::create(test_file).unwrap();
+        let mut tailer = LogTailer::new(test_file).unwrap();
+
+        // Old file gets its last lines, then is rotated and recreated before the next read
+        let mut file = fs::OpenOptions::new().append(true).open(test_file).unwrap();
+        writeln!(file, "old file last line").unwrap();
+        drop(file);
+        fs::rename(test_file, rotated_file).unwrap();
+        let mut file = fs::File::create(test_file).unwrap();
+        writeln!(file, "new file first line").unwrap();
+        drop(file);
+
+        let lines = read(&mut tailer);
+        assert_eq!(lines, vec!["old file last line", "new file first line"]);
+
+        // Clean up
+        fs::remove_file(test_file).ok();
+        fs::remove_file(rotated_file).ok();
+    }
+
+    #[test]
+    fn test_log_tailer_handles_truncate_in_place() {
+        let test_file = "test_log_tailer_truncate.log";
+
+        let mut file = fs::File::create(test_file).unwrap();
+        writeln!(file, "existing line that makes the file long").unwrap();
+        drop(file);
+
+        let mut tailer = LogTailer::new(test_file).unwrap();
+
+        // Truncate in place (copytruncate) and write a shorter line
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(test_file)
+            .unwrap();
+        writeln!(file, "after truncate").unwrap();
+        drop(file);
+
+        let lines = read(&mut tailer);
+        assert_eq!(lines, vec!["after truncate"]);
+
+        // Clean up
+        fs::remove_file(test_file).ok();
+    }
+
+    #[test]
+    fn test_expand_paths_globs_and_literals() {
+        let dir = "test_expand_paths_dir";
+        fs::create_dir_all(dir).unwrap();
+        fs::File::create(format!("{}/a.log", dir)).unwrap();
+        fs::File::create(format!("{}/b.log", dir)).unwrap();
+        fs::File::create(format!("{}/c.txt", dir)).unwrap();
+
+        let patterns = vec![
+            format!("{}/a.log", dir),
+            format!("{}/*.log", dir),
+            "missing.log".to_string(),
+        ];
+        let paths = expand_paths(&patterns).unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from(format!("{}/a.log", dir)),
+                PathBuf::from(format!("{}/b.log", dir)),
+                PathBuf::from("missing.log"),
+            ]
+        );
+
+        // A glob that matches nothing is an error
+        assert!(expand_paths(&[format!("{}/*.gz", dir)]).is_err());
+
+        // Clean up
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_log_tailer_start_positions() {
+        let test_file = "test_log_tailer_start.log";
+
+        let mut file = fs::File::create(test_file).unwrap();
+        for i in 1..=5 {
+            writeln!(
+                file,
+                r#"{{"ClientHost":"10.0.0.{}","StartUTC":"2024-01-15T10:0{}:00Z"}}"#,
+                i, i
+            )
+            .unwrap();
+        }
+        drop(file);
+
+        // Whole file
+        let mut tailer = LogTailer::with_start(test_file, StartPosition::Beginning).unwrap();
+        assert_eq!(read(&mut tailer).len(), 5);
+
+        // Last N lines
+        let mut tailer = LogTailer::with_start(test_file, StartPosition::LastLines(2)).unwrap();
+        let lines = read(&mut tailer);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("10.0.0.4"));
+
+        // More lines than the file has
+        let mut tailer = LogTailer::with_start(test_file, StartPosition::LastLines(50)).unwrap();
+        assert_eq!(read(&mut tailer).len(), 5);
+
+        // Last N bytes skips the partial line it lands in
+        let line_len = fs::read_to_string(test_file)
+            .unwrap()
+            .lines()
+            .next()
+            .unwrap()
+            .len()
+            + 1;
+        let bytes = (line_len + line_len / 2) as u64;
+        let mut tailer = LogTailer::with_start(test_file, StartPosition::LastBytes(bytes)).unwrap();
+        let lines = read(&mut tailer);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains("10.0.0.5"));
+
+        // Since a timestamp
+        let since = DateTime::parse_from_rfc3339("2024-01-15T10:03:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let mut tailer = LogTailer::with_start(test_file, StartPosition::Since(since)).unwrap();
+        let lines = read(&mut tailer);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].contains("10.0.0.3"));
+
+        // Backfill is followed by live tailing
+        let mut file = fs::OpenOptions::new().append(true).open(test_file).unwrap();
+        writeln!(file, "live line").unwrap();
+        drop(file);
+        assert_eq!(read(&mut tailer), vec!["live line"]);
+
+        // Clean up
+        fs::remove_file(test_file).ok();
+    }
+
+    #[test]
+    fn test_log_tailer_resume_from_saved_state() {
+        let test_file = "test_log_tailer_resume.log";
+        let rotated_file = "test_log_tailer_resume.log.1";
+
+        let mut file = fs::File::create(test_file).unwrap();
+        writeln!(file, "read before restart").unwrap();
+        drop(file);
+
+        let mut tailer = LogTailer::with_start(test_file, StartPosition::Beginning).unwrap();
+        assert_eq!(read(&mut tailer).len(), 1);
+        let saved = tailer.state().unwrap();
+        drop(tailer);
+
+        // Lines written while the monitor was down are picked up after resuming
+        let mut file = fs::OpenOptions::new().append(true).open(test_file).unwrap();
+        writeln!(file, "written during restart").unwrap();
+        drop(file);
+
+        let mut tailer = LogTailer::resume(test_file, Some(saved), StartPosition::End).unwrap();
+        assert_eq!(read(&mut tailer), vec!["written during restart"]);
+        let saved = tailer.state().unwrap();
+        drop(tailer);
+
+        // After rotation the saved offset belongs to another file, start the new one fresh
+        fs::rename(test_file, rotated_file).unwrap();
+        let mut file = fs::File::create(test_file).unwrap();
+        writeln!(file, "new file after rotation").unwrap();
+        drop(file);
+
+        let mut tailer = LogTailer::resume(test_file, Some(saved), StartPosition::End).unwrap();
+        assert_eq!(read(&mut tailer), vec!["new file after rotation"]);
+
+        // Without saved state the configured start position applies
+        let mut tailer = LogTailer::resume(test_file, None, StartPosition::End).unwrap();
+        assert!(read(&mut tailer).is_empty());
+
+        // Clean up
+        fs::remove_file(test_file).ok();
+        fs::remove_file(rotated_file).ok();
+    }
+
+    #[test]
+    fn test_log_tailer_buffers_incomplete_line() {
+        let test_file = "test_log_tailer_partial.log";
+
+        fs::File::create(test_file).unwrap();
+        let mut tailer = LogTailer::new(test_file).unwrap();
+
+        // First chunk of a line, no newline yet
+        let mut file = fs::OpenOptions::new().append(true).open(test_file).unwrap();
+        write!(file, r#"{{"ClientHost":"10.0.0.1","#).unwrap();
+        file.flush().unwrap();
+
+        let lines = read(&mut tailer);
+        assert!(lines.is_empty());
+
+        // Rest of the line arrives, followed by a complete line and another partial one
+        writeln!(file, r#""RequestPath":"/api"}}"#).unwrap();
+        write!(file, "second line\nthird").unwrap();
+        file.flush().unwrap();
+
+        let lines = read(&mut tailer);
+        assert_eq!(
+            lines,
+            vec![
+                r#"{"ClientHost":"10.0.0.1","RequestPath":"/api"}"#,
+                "second line"
+            ]
+        );
+
+        writeln!(file, " line").unwrap();
+        drop(file);
+        assert_eq!(read(&mut tailer), vec!["third line"]);
+
+        // Clean up
+        fs::remove_file(test_file).ok();
+    }
+
+    #[test]
+    fn test_log_tailer_tolerates_invalid_utf8() {
+        let test_file = "test_log_tailer_utf8.log";
+
+        fs::File::create(test_file).unwrap();
+        let mut tailer = LogTailer::new(test_file).unwrap();
+
+        let mut file = fs::OpenOptions::new().append(true).open(test_file).unwrap();
+        file.write_all(b"{\"RequestPath\":\"/\xff\xfe\"}\n")
+            .unwrap();
+        writeln!(file, "valid line").unwrap();
+        drop(file);
+
+        // The bad line is decoded lossily and reading continues past it
+        let lines = read(&mut tailer);
+        assert_eq!(
+            lines,
+            vec!["{\"RequestPath\":\"/\u{FFFD}\u{FFFD}\"}", "valid line"]
+        );
+        assert_eq!(tailer.take_invalid_utf8(), 1);
+        assert_eq!(tailer.take_invalid_utf8(), 0);
+
+        // Nothing is re-read on the next call
+        assert!(read(&mut tailer).is_empty());
+
+        // Clean up
+        fs::remove_file(test_file).ok();
+    }
 }
@@ -0,0 +1,166 @@
+use anyhow::{Context, Result};
+use glob::Pattern;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::SyncSender;
+
+use crate::event::MonitorEvent;
+
+/// Filesystem-notification watcher for tailed log files (inotify on Linux,
+/// ReadDirectoryChangesW on Windows)
+/// Watches the parent directory of every file so that rotation (rename and recreate)
+/// is noticed too, and sends `MonitorEvent::FilesChanged` when a tailed file changes.
+/// Can also watch a whole directory for files matching a pattern, including new ones.
+/// Notifications are coalesced: after one `FilesChanged` is sent, further changes send
+/// nothing until the loop calls `rearm`, so a burst of writes queues a single event.
+pub struct FileWatcher {
+    // Kept alive for as long as notifications are wanted
+    _watcher: RecommendedWatcher,
+    /// Set while a `FilesChanged` event is queued and not yet handled
+    pending: Arc<AtomicBool>,
+}
+
+impl FileWatcher {
+    /// Start watching the given files, and optionally every file in a directory whose
+    /// name matches a pattern, sending change events on `tx`
+    /// Fails if the platform has no notification backend, in which case the
+    /// caller falls back to polling
+    pub fn new(
+        paths: &[PathBuf],
+        directory: Option<(&Path, &Pattern)>,
+        tx: SyncSender<MonitorEvent>,
+    ) -> Result<Self> {
+        let mut files = HashSet::new();
+        let mut dirs = HashSet::new();
+        for path in paths {
+            let file = std::path::absolute(path)
+                .context(format!("Failed to resolve path: {}", path.display()))?;
+            if let Some(dir) = file.parent() {
+                dirs.insert(dir.to_path_buf());
+            }
+            files.insert(file);
+        }
+
+        let directory = match directory {
+            Some((dir, pattern)) => {
+                let dir = std::path::absolute(dir)
+                    .context(format!("Failed to resolve path: {}", dir.display()))?;
+                dirs.insert(dir.clone());
+                Some((dir, pattern.clone()))
+            }
+            None => None,
+        };
+
+        let pending = Arc::new(AtomicBool::new(false));
+        let queued = pending.clone();
+        let mut watcher = notify::recommended_watcher(move |result: notify::Result<Event>| {
+            // Errors from the backend are dropped, the loop's fallback poll covers them
+            let Ok(event) = result else {
+                return;
+            };
+
+            // Directory watches report every file in the directory, keep only ours
+            let relevant = event.paths.iter().any(|path| {
+                is_tailed(&files, path)
+                    || directory
+                        .as_ref()
+                        .is_some_and(|(dir, pattern)| in_directory(dir, pattern, path))
+            });
+            // Only the change that finds nothing queued sends an event
+            if relevant && !queued.swap(true, Ordering::SeqCst) {
+                let _ = tx.send(MonitorEvent::FilesChanged);
+            }
+        })
+        .context("Failed to create file watcher")?;
+
+        for dir in &dirs {
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .context(format!("Failed to watch directory: {}", dir.display()))?;
+        }
+
+        Ok(FileWatcher {
+            _watcher: watcher,
+            pending,
+        })
+    }
+
+    /// Let the next change send another `FilesChanged`
+    /// Called when the event is received, before the files are read, so a change made
+    /// during the read is not missed
+    pub fn rearm(&self) {
+        self.pending.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Check whether an event path refers to one of the tailed files
+fn is_tailed(files: &HashSet<PathBuf>, path: &Path) -> bool {
+    files.contains(path)
+        || std::path::absolute(path)
+            .map(|path| files.contains(&path))
+            .unwrap_or(false)
+}
+
+/// Check whether an event path is a file in the watched directory matching the pattern
+fn in_directory(dir: &Path, pattern: &Pattern, path: &Path) -> bool {
+    path.parent() == Some(dir)
+        && path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| pattern.matches(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_watcher_reports_writes_to_tailed_file_only() {
+        let dir = "test_file_watcher_dir";
+        fs::create_dir_all(dir).unwrap();
+        let tailed = PathBuf::from(format!("{}/access.log", dir));
+        let other = PathBuf::from(format!("{}/other.log", dir));
+        fs::File::create(&tailed).unwrap();
+        fs::File::create(&other).unwrap();
+
+        let (tx, rx) = mpsc::sync_channel(16);
+        let watcher = FileWatcher::new(std::slice::from_ref(&tailed), None, tx).unwrap();
+
+        // Writes to another file in the same directory are ignored
+        let mut file = fs::OpenOptions::new().append(true).open(&other).unwrap();
+        writeln!(file, "not tailed").unwrap();
+        drop(file);
+        assert!(rx.recv_timeout(Duration::from_millis(300)).is_err());
+
+        // Writes to the tailed file wake the loop, once for a whole burst
+        for _ in 0..20 {
+            let mut file = fs::OpenOptions::new().append(true).open(&tailed).unwrap();
+            writeln!(file, "tailed").unwrap();
+        }
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(5)).unwrap(),
+            MonitorEvent::FilesChanged
+        );
+        assert!(rx.recv_timeout(Duration::from_millis(300)).is_err());
+
+        // Once rearmed, the next write sends another event
+        watcher.rearm();
+        let mut file = fs::OpenOptions::new().append(true).open(&tailed).unwrap();
+        writeln!(file, "tailed").unwrap();
+        drop(file);
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(5)).unwrap(),
+            MonitorEvent::FilesChanged
+        );
+
+        // Clean up
+        fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,187 @@
+use anyhow::{Result, bail};
+
+use crate::clf::parse_clf_line;
+use crate::log_entry::{BorrowedEntry, TraefikLogEntry};
+use crate::statistics::StatsCollector;
+
+/// Lines sampled from the start of an input to detect its format
+pub const DETECT_SAMPLE_LINES: usize = 16;
+
+/// An access log line format that can be turned into `TraefikLogEntry`s
+/// New formats (e.g. nginx or Caddy JSON) implement this trait and are added to
+/// `FORMATS`; detection and the `--format` option pick them up from there
+pub trait LogFormat: Sync {
+    /// Name used on the command line
+    fn name(&self) -> &'static str;
+
+    /// Whether a line looks like this format, used for detection
+    fn matches(&self, line: &str) -> bool;
+
+    /// Parse one line
+    fn parse(&self, line: &str) -> Result<TraefikLogEntry>;
+
+    /// Parse one line read from `source` and add it to the statistics
+    /// Formats with a cheaper path than building a full entry override this
+    fn add_line(&self, line: &str, source: &str, stats: &mut StatsCollector) -> Result<()> {
+        let mut entry = self.parse(line)?;
+        entry.source = Some(source.to_string());
+        stats.add_entry(&entry);
+        Ok(())
+    }
+}
+
+/// Traefik's JSON access log format
+pub struct JsonFormat;
+
+impl LogFormat for JsonFormat {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn matches(&self, line: &str) -> bool {
+        line.starts_with('{') && line.ends_with('}')
+    }
+
+    fn parse(&self, line: &str) -> Result<TraefikLogEntry> {
+        TraefikLogEntry::from_json_line(line)
+    }
+
+    fn add_line(&self, line: &str, source: &str, stats: &mut StatsCollector) -> Result<()> {
+        stats.add_borrowed(&BorrowedEntry::from_json_line(line)?, source);
+        Ok(())
+    }
+}
+
+/// Traefik's CLF (common) text access log format
+pub struct ClfFormat;
+
+impl LogFormat for ClfFormat {
+    fn name(&self) -> &'static str {
+        "clf"
+    }
+
+    fn matches(&self, line: &str) -> bool {
+        parse_clf_line(line).is_ok()
+    }
+
+    fn parse(&self, line: &str) -> Result<TraefikLogEntry> {
+        parse_clf_line(line)
+    }
+}
+
+/// All known formats; the first one is the fallback when nothing matches
+pub const FORMATS: &[&dyn LogFormat] = &[&JsonFormat, &ClfFormat];
+
+/// Look up a format by its command-line name
+pub fn format_by_name(name: &str) -> Result<&'static dyn LogFormat> {
+    match FORMATS.iter().find(|format| format.name() == name) {
+        Some(format) => Ok(*format),
+        None => {
+            let names: Vec<_> = FORMATS.iter().map(|format| format.name()).collect();
+            bail!(
+                "Unknown format: {} (expected auto, {})",
+                name,
+                names.join(", ")
+            )
+        }
+    }
+}
+
+/// Detects the format of one input from its first lines, one line at a time
+/// Every line votes for the format it matches; the format matching most of them wins
+/// once `DETECT_SAMPLE_LINES` lines were seen, so one odd first line (a banner, a stray
+/// line in another format) does not decide the format on its own
+#[derive(Debug, Clone)]
+pub struct FormatDetector {
+    votes: Vec<usize>,
+    sampled: usize,
+}
+
+impl Default for FormatDetector {
+    fn default() -> Self {
+        FormatDetector {
+            votes: vec![0; FORMATS.len()],
+            sampled: 0,
+        }
+    }
+}
+
+impl FormatDetector {
+    /// Count one line's vote and return the format it matches, if any
+    pub fn observe(&mut self, line: &str) -> Option<&'static dyn LogFormat> {
+        self.sampled += 1;
+        let index = FORMATS.iter().position(|format| format.matches(line))?;
+        self.votes[index] += 1;
+        Some(FORMATS[index])
+    }
+
+    /// Whether the whole sample has been seen
+    pub fn is_complete(&self) -> bool {
+        self.sampled >= DETECT_SAMPLE_LINES
+    }
+
+    /// The format matching most of the lines seen so far, ties going to the earlier
+    /// format, or None if no format matched any of them
+    pub fn decide(&self) -> Option<&'static dyn LogFormat> {
+        let (index, &count) = self
+            .votes
+            .iter()
+            .enumerate()
+            .max_by_key(|&(index, count)| (*count, std::cmp::Reverse(index)))?;
+        (count > 0).then_some(FORMATS[index])
+    }
+}
+
+/// Detect the format matching most of the sampled lines
+/// Returns None if no format matches any of them
+pub fn detect_format<'a, I>(sample: I) -> Option<&'static dyn LogFormat>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut detector = FormatDetector::default();
+    for line in sample.into_iter().take(DETECT_SAMPLE_LINES) {
+        detector.observe(line);
+    }
+    detector.decide()
+}
+
+/// Parse a single line of unknown format, detecting the format from the line itself
+/// For one-off lookups (e.g. finding a timestamp); inputs are detected once instead
+pub fn parse_line(line: &str) -> Result<TraefikLogEntry> {
+    detect_format([line]).unwrap_or(FORMATS[0]).parse(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JSON_LINE: &str = r#"{"ClientHost":"10.0.0.1","RequestPath":"/api"}"#;
+    const CLF_LINE: &str = r#"10.0.0.1 - - [15/Jan/2024:10:30:00 +0000] "GET /api HTTP/1.1" 200 12 "-" "curl/8.0" 1 "api@docker" "http://10.0.0.2:80" 3ms"#;
+
+    #[test]
+    fn test_detect_format_by_majority() {
+        assert_eq!(
+            detect_format([JSON_LINE, JSON_LINE]).unwrap().name(),
+            "json"
+        );
+        assert_eq!(
+            detect_format([CLF_LINE, "garbage", CLF_LINE, JSON_LINE])
+                .unwrap()
+                .name(),
+            "clf"
+        );
+        assert!(detect_format(["garbage"]).is_none());
+        assert!(detect_format([]).is_none());
+    }
+
+    #[test]
+    fn test_formats_parse_the_same_entry() {
+        let json = format_by_name("json").unwrap().parse(JSON_LINE).unwrap();
+        let clf = format_by_name("clf").unwrap().parse(CLF_LINE).unwrap();
+        assert_eq!(json.get_ip(), clf.get_ip());
+        assert_eq!(json.get_path(), clf.get_path());
+
+        assert!(format_by_name("nginx").is_err());
+        assert_eq!(parse_line(CLF_LINE).unwrap().downstream_status, Some(200));
+    }
+}
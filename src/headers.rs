@@ -0,0 +1,250 @@
+use serde::de::{Deserialize, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Value Traefik writes for headers logged in `redact` mode
+pub const REDACTED: &str = "REDACTED";
+
+/// Value of a logged header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderValue {
+    /// The header value as sent
+    Text(String),
+    /// Traefik replaced the value with "REDACTED"
+    Redacted,
+}
+
+impl HeaderValue {
+    /// Wrap a logged value, recognising Traefik's redaction marker
+    pub fn from_logged(value: String) -> Self {
+        if value == REDACTED {
+            HeaderValue::Redacted
+        } else {
+            HeaderValue::Text(value)
+        }
+    }
+}
+
+/// HTTP headers of one side of a request, with case-insensitive names
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Headers {
+    // Keyed by lowercased name
+    values: HashMap<String, HeaderValue>,
+}
+
+impl Headers {
+    /// Set a header, replacing any value under the same name in another case
+    pub fn insert(&mut self, name: &str, value: HeaderValue) {
+        self.values.insert(name.to_ascii_lowercase(), value);
+    }
+
+    /// Look up a header by name, ignoring case
+    pub fn get(&self, name: &str) -> Option<&HeaderValue> {
+        self.values.get(&name.to_ascii_lowercase())
+    }
+
+    /// Text of a header, or None if it is missing or redacted
+    pub fn value(&self, name: &str) -> Option<&str> {
+        match self.get(name)? {
+            HeaderValue::Text(text) => Some(text),
+            HeaderValue::Redacted => None,
+        }
+    }
+
+    /// Whether a header was logged but redacted by Traefik
+    #[cfg(test)]
+    pub fn is_redacted(&self, name: &str) -> bool {
+        self.get(name) == Some(&HeaderValue::Redacted)
+    }
+
+    /// Number of headers
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Whether no headers were logged
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// Headers Traefik logs with `accessLog.fields.headers`, one map per side
+/// JSON keys are `request_<Name>` (from the client), `origin_<Name>` (from the backend)
+/// and `downstream_<Name>` (sent back to the client)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoggedHeaders {
+    pub request: Headers,
+    pub origin: Headers,
+    pub downstream: Headers,
+}
+
+/// Side of a request a logged header was sent on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    /// From the client (`request_<Name>`)
+    Request,
+    /// From the backend (`origin_<Name>`)
+    Origin,
+    /// Sent back to the client (`downstream_<Name>`)
+    Downstream,
+}
+
+/// Split a log entry key into the side and name of the header it holds
+/// (`request_User-Agent` is `User-Agent` on the request side), or None if it is not a
+/// header key. Both ingestion paths go through this, so they agree on header keys
+pub fn split_header_key(key: &str) -> Option<(Side, &str)> {
+    if let Some(name) = key.strip_prefix("request_") {
+        Some((Side::Request, name))
+    } else if let Some(name) = key.strip_prefix("origin_") {
+        Some((Side::Origin, name))
+    } else {
+        key.strip_prefix("downstream_")
+            .map(|name| (Side::Downstream, name))
+    }
+}
+
+/// A logged header value as it appears in the JSON, borrowed when it has no escapes
+/// Anything but a string (null, or a mis-typed value) is no value, so a header of the
+/// wrong type is skipped rather than rejecting the line
+pub struct LoggedText<'a>(pub Option<Cow<'a, str>>);
+
+impl<'de> Deserialize<'de> for LoggedText<'de> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TextVisitor;
+
+        impl<'de> Visitor<'de> for TextVisitor {
+            type Value = LoggedText<'de>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a header value")
+            }
+
+            fn visit_borrowed_str<E>(self, value: &'de str) -> Result<Self::Value, E> {
+                Ok(LoggedText(Some(Cow::Borrowed(value))))
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E> {
+                Ok(LoggedText(Some(Cow::Owned(value.to_string()))))
+            }
+
+            fn visit_string<E>(self, value: String) -> Result<Self::Value, E> {
+                Ok(LoggedText(Some(Cow::Owned(value))))
+            }
+
+            fn visit_some<D: Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<Self::Value, D::Error> {
+                LoggedText::deserialize(deserializer)
+            }
+
+            fn visit_none<E>(self) -> Result<Self::Value, E> {
+                Ok(LoggedText(None))
+            }
+
+            fn visit_unit<E>(self) -> Result<Self::Value, E> {
+                Ok(LoggedText(None))
+            }
+
+            fn visit_bool<E>(self, _: bool) -> Result<Self::Value, E> {
+                Ok(LoggedText(None))
+            }
+
+            fn visit_i64<E>(self, _: i64) -> Result<Self::Value, E> {
+                Ok(LoggedText(None))
+            }
+
+            fn visit_u64<E>(self, _: u64) -> Result<Self::Value, E> {
+                Ok(LoggedText(None))
+            }
+
+            fn visit_f64<E>(self, _: f64) -> Result<Self::Value, E> {
+                Ok(LoggedText(None))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                while seq.next_element::<IgnoredAny>()?.is_some() {}
+                Ok(LoggedText(None))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                while map.next_entry::<IgnoredAny, IgnoredAny>()?.is_some() {}
+                Ok(LoggedText(None))
+            }
+        }
+
+        deserializer.deserialize_any(TextVisitor)
+    }
+}
+
+impl LoggedHeaders {
+    /// Map of the headers on one side
+    fn side_mut(&mut self, side: Side) -> &mut Headers {
+        match side {
+            Side::Request => &mut self.request,
+            Side::Origin => &mut self.origin,
+            Side::Downstream => &mut self.downstream,
+        }
+    }
+}
+
+/// Collects the header keys of a log entry, ignoring every other key
+/// Used with `#[serde(flatten)]`, so it sees the keys no regular field claimed
+impl<'de> Deserialize<'de> for LoggedHeaders {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct HeaderVisitor;
+
+        impl<'de> Visitor<'de> for HeaderVisitor {
+            type Value = LoggedHeaders;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map of log fields")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut headers = LoggedHeaders::default();
+                while let Some(key) = map.next_key::<String>()? {
+                    match split_header_key(&key) {
+                        Some((side, name)) => {
+                            if let LoggedText(Some(value)) = map.next_value()? {
+                                let value = HeaderValue::from_logged(value.into_owned());
+                                headers.side_mut(side).insert(name, value);
+                            }
+                        }
+                        None => {
+                            map.next_value::<IgnoredAny>()?;
+                        }
+                    }
+                }
+                Ok(headers)
+            }
+        }
+
+        deserializer.deserialize_map(HeaderVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headers_are_case_insensitive_and_redactable() {
+        let mut headers = Headers::default();
+        headers.insert("User-Agent", HeaderValue::Text("curl/8.0".to_string()));
+        headers.insert(
+            "Authorization",
+            HeaderValue::from_logged(REDACTED.to_string()),
+        );
+
+        assert_eq!(headers.value("user-agent"), Some("curl/8.0"));
+        assert_eq!(headers.value("USER-AGENT"), Some("curl/8.0"));
+        assert!(headers.is_redacted("authorization"));
+        assert_eq!(headers.value("Authorization"), None);
+        assert!(headers.get("Referer").is_none());
+        assert_eq!(headers.len(), 2);
+    }
+}
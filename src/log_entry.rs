@@ -1,7 +1,19 @@
+use chrono::{DateTime, FixedOffset, Utc};
 use serde::Deserialize;
+use serde::de::{self, Deserializer, IgnoredAny, MapAccess, Visitor};
+use std::borrow::Cow;
+use std::fmt;
+use std::net::IpAddr;
 
-/// Represents a Traefik access log entry in JSON format
-#[derive(Debug, Deserialize)]
+use crate::client_ip::{FORWARDED_FOR, REAL_IP, parse_ip};
+use crate::headers::{LoggedHeaders, LoggedText, REDACTED, Side, split_header_key};
+
+/// Represents a Traefik access log entry (JSON fields; also produced from CLF lines)
+/// Covers the documented access log fields; durations are in nanoseconds and sizes
+/// in bytes, as Traefik writes them. A field of the wrong type is left empty instead
+/// of rejecting the line, except for the client address, path and start times the
+/// statistics are built from.
+#[derive(Debug, Default, Deserialize)]
 #[allow(dead_code)]
 pub struct TraefikLogEntry {
     #[serde(rename = "ClientAddr")]
@@ -10,54 +22,435 @@ pub struct TraefikLogEntry {
     #[serde(rename = "ClientHost")]
     pub client_host: Option<String>,
 
+    #[serde(rename = "ClientPort", default, deserialize_with = "lenient")]
+    pub client_port: Option<String>,
+
+    /// Authenticated user, "-" when there is none
+    #[serde(rename = "ClientUsername", default, deserialize_with = "lenient")]
+    pub client_username: Option<String>,
+
+    #[serde(rename = "RequestAddr", default, deserialize_with = "lenient")]
+    pub request_addr: Option<String>,
+
+    #[serde(rename = "RequestHost", default, deserialize_with = "lenient")]
+    pub request_host: Option<String>,
+
+    /// Port from the Host header, "-" when there is none
+    #[serde(rename = "RequestPort", default, deserialize_with = "lenient")]
+    pub request_port: Option<String>,
+
     #[serde(rename = "RequestPath")]
     pub request_path: Option<String>,
 
-    #[serde(rename = "RequestMethod")]
+    #[serde(rename = "RequestMethod", default, deserialize_with = "lenient")]
     pub request_method: Option<String>,
 
-    #[serde(rename = "RequestProtocol")]
+    #[serde(rename = "RequestProtocol", default, deserialize_with = "lenient")]
     pub request_protocol: Option<String>,
 
-    #[serde(rename = "OriginStatus")]
+    #[serde(rename = "RequestScheme", default, deserialize_with = "lenient")]
+    pub request_scheme: Option<String>,
+
+    #[serde(rename = "RequestContentSize", default, deserialize_with = "lenient")]
+    pub request_content_size: Option<u64>,
+
+    /// Number of requests Traefik has received since it started
+    #[serde(rename = "RequestCount", default, deserialize_with = "lenient")]
+    pub request_count: Option<u64>,
+
+    #[serde(rename = "entryPointName", default, deserialize_with = "lenient")]
+    pub entry_point_name: Option<String>,
+
+    #[serde(rename = "RouterName", default, deserialize_with = "lenient")]
+    pub router_name: Option<String>,
+
+    #[serde(rename = "ServiceName", default, deserialize_with = "lenient")]
+    pub service_name: Option<String>,
+
+    /// Backend URL; older Traefik versions write it as an object, newer ones as a string
+    #[serde(rename = "ServiceURL", default, deserialize_with = "service_url")]
+    pub service_url: Option<String>,
+
+    #[serde(rename = "ServiceAddr", default, deserialize_with = "lenient")]
+    pub service_addr: Option<String>,
+
+    #[serde(rename = "OriginStatus", default, deserialize_with = "lenient")]
     pub origin_status: Option<u16>,
 
-    #[serde(rename = "DownstreamStatus")]
+    #[serde(rename = "OriginContentSize", default, deserialize_with = "lenient")]
+    pub origin_content_size: Option<u64>,
+
+    /// Time spent waiting for the backend (ns)
+    #[serde(rename = "OriginDuration", default, deserialize_with = "lenient")]
+    pub origin_duration: Option<u64>,
+
+    #[serde(rename = "DownstreamStatus", default, deserialize_with = "lenient")]
     pub downstream_status: Option<u16>,
+
+    #[serde(
+        rename = "DownstreamContentSize",
+        default,
+        deserialize_with = "lenient"
+    )]
+    pub downstream_content_size: Option<u64>,
+
+    /// Total time taken by the request (ns)
+    #[serde(rename = "Duration", default, deserialize_with = "lenient")]
+    pub duration: Option<u64>,
+
+    /// Time spent in Traefik itself, i.e. Duration minus OriginDuration (ns)
+    #[serde(rename = "Overhead", default, deserialize_with = "lenient")]
+    pub overhead: Option<u64>,
+
+    #[serde(rename = "RetryAttempts", default, deserialize_with = "lenient")]
+    pub retry_attempts: Option<u32>,
+
+    #[serde(rename = "GzipRatio", default, deserialize_with = "lenient")]
+    pub gzip_ratio: Option<f64>,
+
+    #[serde(rename = "StartUTC", default, deserialize_with = "rfc3339")]
+    pub start_utc: Option<DateTime<Utc>>,
+
+    #[serde(rename = "StartLocal", default, deserialize_with = "rfc3339")]
+    pub start_local: Option<DateTime<FixedOffset>>,
+
+    /// When the request started: StartUTC, else StartLocal
+    /// None if neither was logged; the statistics then use the time the entry is counted
+    #[serde(skip)]
+    pub timestamp: Option<DateTime<Utc>>,
+
+    #[serde(rename = "TLSVersion", default, deserialize_with = "lenient")]
+    pub tls_version: Option<String>,
+
+    #[serde(rename = "TLSCipher", default, deserialize_with = "lenient")]
+    pub tls_cipher: Option<String>,
+
+    #[serde(rename = "TLSClientSubject", default, deserialize_with = "lenient")]
+    pub tls_client_subject: Option<String>,
+
+    #[serde(rename = "TraceId", default, deserialize_with = "lenient")]
+    pub trace_id: Option<String>,
+
+    #[serde(rename = "SpanId", default, deserialize_with = "lenient")]
+    pub span_id: Option<String>,
+
+    /// Headers kept by Traefik's header logging (`request_*`, `origin_*`, `downstream_*`)
+    #[serde(flatten)]
+    pub headers: LoggedHeaders,
+
+    /// Input the entry was read from (e.g. the log file path), set by the reader
+    #[serde(skip)]
+    pub source: Option<String>,
+}
+
+/// ServiceURL as written by Traefik: a URL string, or Go's url.URL struct in older versions
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ServiceUrl {
+    Text(String),
+    Parts {
+        #[serde(rename = "Scheme", default)]
+        scheme: String,
+        #[serde(rename = "Host", default)]
+        host: String,
+        #[serde(rename = "Path", default)]
+        path: String,
+    },
+    /// Neither, ignored like other mis-typed fields
+    Invalid(IgnoredAny),
+}
+
+/// Deserialize ServiceURL from either representation into a URL string
+fn service_url<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let url = Option::<ServiceUrl>::deserialize(deserializer)?.and_then(|url| match url {
+        ServiceUrl::Text(text) => Some(text),
+        ServiceUrl::Parts { scheme, host, path } if scheme.is_empty() => {
+            Some(format!("{}{}", host, path))
+        }
+        ServiceUrl::Parts { scheme, host, path } => Some(format!("{}://{}{}", scheme, host, path)),
+        ServiceUrl::Invalid(_) => None,
+    });
+    Ok(url)
+}
+
+/// Deserialize an RFC 3339 timestamp (nanosecond precision)
+/// Unparseable values become None rather than failing the whole entry
+fn rfc3339<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: From<DateTime<FixedOffset>>,
+{
+    let time = Option::<String>::deserialize(deserializer)?
+        .and_then(|time| DateTime::parse_from_rfc3339(&time).ok())
+        .map(T::from);
+    Ok(time)
+}
+
+/// Deserialize a field, leaving it empty if the value has the wrong type or range
+/// (e.g. `"DownstreamStatus":"200"`), like `rfc3339` does for timestamps
+fn lenient<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Lenient<T> {
+        Valid(T),
+        Invalid(IgnoredAny),
+    }
+
+    let value = match Option::<Lenient<T>>::deserialize(deserializer)? {
+        Some(Lenient::Valid(value)) => Some(value),
+        Some(Lenient::Invalid(_)) | None => None,
+    };
+    Ok(value)
 }
 
 impl TraefikLogEntry {
     /// Parse a JSON line into a TraefikLogEntry
     pub fn from_json_line(line: &str) -> anyhow::Result<Self> {
         let entry: TraefikLogEntry = serde_json::from_str(line)?;
-        Ok(entry)
+        Ok(entry.with_timestamp())
+    }
+
+    /// Set `timestamp` from the logged start time
+    /// Called by every parser once the entry's fields are filled in
+    pub fn with_timestamp(mut self) -> Self {
+        self.timestamp = self.start_time();
+        self
     }
 
     /// Extract the IP address from the log entry
-    /// Prefers ClientHost, falls back to ClientAddr (removing port if present)
-    pub fn get_ip(&self) -> Option<String> {
-        // Try ClientHost first
-        if let Some(ref host) = self.client_host
-            && !host.is_empty()
-        {
-            return Some(host.clone());
+    /// Prefers ClientHost, falls back to ClientAddr; either may carry a port, brackets
+    /// or an IPv6 zone, which are stripped by `parse_ip`
+    /// Returns None if neither holds a valid address
+    pub fn get_ip(&self) -> Option<IpAddr> {
+        self.client_host
+            .as_deref()
+            .and_then(parse_ip)
+            .or_else(|| self.client_addr.as_deref().and_then(parse_ip))
+    }
+
+    /// Logged request start time, from StartUTC or else StartLocal
+    pub fn start_time(&self) -> Option<DateTime<Utc>> {
+        self.start_utc
+            .or_else(|| self.start_local.map(|time| time.with_timezone(&Utc)))
+    }
+
+    /// Get the request path, defaulting to "/" if not present
+    pub fn get_path(&self) -> &str {
+        self.request_path.as_deref().unwrap_or("/")
+    }
+}
+
+/// The fields of a JSON access log line that the statistics use, borrowed from the line
+/// The hot ingestion path: every other field is skipped without being copied, and
+/// strings are only allocated when they contain JSON escapes. It accepts exactly the
+/// lines `TraefikLogEntry` accepts, and finds the forwarding headers under any case
+/// of their name, like `Headers` does.
+#[derive(Debug, Default)]
+pub struct BorrowedEntry<'a> {
+    pub client_addr: Option<Cow<'a, str>>,
+    pub client_host: Option<Cow<'a, str>>,
+    pub request_path: Option<Cow<'a, str>>,
+    pub start_utc: Option<Cow<'a, str>>,
+    pub start_local: Option<Cow<'a, str>>,
+    /// X-Forwarded-For request header, None if not logged or redacted
+    pub forwarded_for: Option<Cow<'a, str>>,
+    /// X-Real-Ip request header, None if not logged or redacted
+    pub real_ip: Option<Cow<'a, str>>,
+}
+
+impl<'a> BorrowedEntry<'a> {
+    /// Parse a JSON line, borrowing from it
+    pub fn from_json_line(line: &'a str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(line)?)
+    }
+
+    /// Extract the IP address, like `TraefikLogEntry::get_ip`
+    pub fn get_ip(&self) -> Option<IpAddr> {
+        self.client_host
+            .as_deref()
+            .and_then(parse_ip)
+            .or_else(|| self.client_addr.as_deref().and_then(parse_ip))
+    }
+
+    /// Logged request start time, from StartUTC or else StartLocal
+    /// Unparseable values are ignored, like in `TraefikLogEntry`
+    pub fn start_time(&self) -> Option<DateTime<Utc>> {
+        let parse = |time: &Option<Cow<str>>| {
+            DateTime::parse_from_rfc3339(time.as_deref()?)
+                .ok()
+                .map(|time| time.with_timezone(&Utc))
+        };
+        parse(&self.start_utc).or_else(|| parse(&self.start_local))
+    }
+
+    /// Get the request path, defaulting to "/" if not present
+    pub fn get_path(&self) -> &str {
+        self.request_path.as_deref().unwrap_or("/")
+    }
+}
+
+/// Keys of a log line that `BorrowedEntry` reads
+enum BorrowedKey {
+    ClientAddr,
+    ClientHost,
+    RequestPath,
+    StartUtc,
+    StartLocal,
+    ForwardedFor,
+    RealIp,
+    Other,
+}
+
+impl<'de> Deserialize<'de> for BorrowedKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeyVisitor;
+
+        impl Visitor<'_> for KeyVisitor {
+            type Value = BorrowedKey;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a log field name")
+            }
+
+            fn visit_str<E>(self, key: &str) -> Result<Self::Value, E> {
+                let key = match key {
+                    "ClientAddr" => BorrowedKey::ClientAddr,
+                    "ClientHost" => BorrowedKey::ClientHost,
+                    "RequestPath" => BorrowedKey::RequestPath,
+                    "StartUTC" => BorrowedKey::StartUtc,
+                    "StartLocal" => BorrowedKey::StartLocal,
+                    key => match split_header_key(key) {
+                        Some((Side::Request, name)) if name.eq_ignore_ascii_case(FORWARDED_FOR) => {
+                            BorrowedKey::ForwardedFor
+                        }
+                        Some((Side::Request, name)) if name.eq_ignore_ascii_case(REAL_IP) => {
+                            BorrowedKey::RealIp
+                        }
+                        _ => BorrowedKey::Other,
+                    },
+                };
+                Ok(key)
+            }
         }
 
-        // Fall back to ClientAddr, strip port if present
-        if let Some(ref addr) = self.client_addr {
-            if let Some(colon_pos) = addr.rfind(':') {
-                // Extract IP part before the port
-                return Some(addr[..colon_pos].to_string());
+        deserializer.deserialize_identifier(KeyVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for BorrowedEntry<'de> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EntryVisitor;
+
+        impl<'de> Visitor<'de> for EntryVisitor {
+            type Value = BorrowedEntry<'de>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a Traefik access log entry")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                // Fields are strict strings and may appear once, headers are lenient and
+                // the last one logged counts, both as in `TraefikLogEntry`
+                let mut fields: [Option<Option<Cow<'de, str>>>; 5] = Default::default();
+                let mut entry = BorrowedEntry::default();
+
+                while let Some(key) = map.next_key::<BorrowedKey>()? {
+                    let (index, name) = match key {
+                        BorrowedKey::ClientAddr => (0, "ClientAddr"),
+                        BorrowedKey::ClientHost => (1, "ClientHost"),
+                        BorrowedKey::RequestPath => (2, "RequestPath"),
+                        BorrowedKey::StartUtc => (3, "StartUTC"),
+                        BorrowedKey::StartLocal => (4, "StartLocal"),
+                        BorrowedKey::ForwardedFor => {
+                            read_header(&mut map, &mut entry.forwarded_for)?;
+                            continue;
+                        }
+                        BorrowedKey::RealIp => {
+                            read_header(&mut map, &mut entry.real_ip)?;
+                            continue;
+                        }
+                        BorrowedKey::Other => {
+                            map.next_value::<IgnoredAny>()?;
+                            continue;
+                        }
+                    };
+                    if fields[index].is_some() {
+                        return Err(de::Error::duplicate_field(name));
+                    }
+                    let value = map.next_value::<Option<CowStr<'de>>>()?;
+                    fields[index] = Some(value.map(|value| value.0));
+                }
+
+                let [
+                    client_addr,
+                    client_host,
+                    request_path,
+                    start_utc,
+                    start_local,
+                ] = fields.map(Option::flatten);
+                Ok(BorrowedEntry {
+                    client_addr,
+                    client_host,
+                    request_path,
+                    start_utc,
+                    start_local,
+                    ..entry
+                })
             }
-            return Some(addr.clone());
         }
 
-        None
+        deserializer.deserialize_map(EntryVisitor)
     }
+}
 
-    /// Get the request path, defaulting to "/" if not present
-    pub fn get_path(&self) -> String {
-        self.request_path.clone().unwrap_or_else(|| "/".to_string())
+/// Read a logged request header into `header`, unless the value is not a string
+/// A redacted value clears it, like `Headers::value` returns None for it
+fn read_header<'de, A: MapAccess<'de>>(
+    map: &mut A,
+    header: &mut Option<Cow<'de, str>>,
+) -> Result<(), A::Error> {
+    if let LoggedText(Some(value)) = map.next_value()? {
+        *header = (value != REDACTED).then_some(value);
+    }
+    Ok(())
+}
+
+/// A string borrowed from the input when it has no escapes
+/// (serde only borrows a bare `Cow<str>` field, not one inside an `Option`)
+struct CowStr<'a>(Cow<'a, str>);
+
+impl<'de> Deserialize<'de> for CowStr<'de> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct CowVisitor;
+
+        impl<'de> Visitor<'de> for CowVisitor {
+            type Value = CowStr<'de>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a string")
+            }
+
+            fn visit_borrowed_str<E>(self, value: &'de str) -> Result<Self::Value, E> {
+                Ok(CowStr(Cow::Borrowed(value)))
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E> {
+                Ok(CowStr(Cow::Owned(value.to_string())))
+            }
+
+            fn visit_string<E>(self, value: String) -> Result<Self::Value, E> {
+                Ok(CowStr(Cow::Owned(value)))
+            }
+        }
+
+        deserializer.deserialize_str(CowVisitor)
     }
 }
 
@@ -70,7 +463,7 @@ mod tests {
         let json =
             r#"{"ClientHost":"192.168.1.100","RequestPath":"/api/users","OriginStatus":200}"#;
         let entry = TraefikLogEntry::from_json_line(json).unwrap();
-        assert_eq!(entry.get_ip(), Some("192.168.1.100".to_string()));
+        assert_eq!(entry.get_ip(), "192.168.1.100".parse().ok());
         assert_eq!(entry.get_path(), "/api/users");
     }
 
@@ -78,7 +471,46 @@ mod tests {
     fn test_extract_ip_from_client_addr() {
         let json = r#"{"ClientAddr":"10.0.0.1:54321","RequestPath":"/test"}"#;
         let entry = TraefikLogEntry::from_json_line(json).unwrap();
-        assert_eq!(entry.get_ip(), Some("10.0.0.1".to_string()));
+        assert_eq!(entry.get_ip(), "10.0.0.1".parse().ok());
+    }
+
+    #[test]
+    fn test_get_ip_address_shapes() {
+        // (ClientHost, ClientAddr, expected address)
+        let cases = [
+            (
+                Some("192.168.1.100"),
+                Some("192.168.1.100:54321"),
+                Some("192.168.1.100"),
+            ),
+            (None, Some("192.168.1.100:54321"), Some("192.168.1.100")),
+            (
+                Some("2001:db8::1"),
+                Some("[2001:db8::1]:443"),
+                Some("2001:db8::1"),
+            ),
+            (None, Some("[2001:db8::1]:443"), Some("2001:db8::1")),
+            (None, Some("[::1]:8080"), Some("::1")),
+            (Some("::1"), None, Some("::1")),
+            (None, Some("[fe80::1%eth0]:443"), Some("fe80::1")),
+            (Some("fe80::1%eth0"), None, Some("fe80::1")),
+            (None, Some("[::ffff:192.0.2.7]:443"), Some("192.0.2.7")),
+            (Some("::ffff:192.0.2.7"), None, Some("192.0.2.7")),
+            (Some(""), Some("10.0.0.1:80"), Some("10.0.0.1")),
+            (Some("not-an-ip"), Some("10.0.0.1:80"), Some("10.0.0.1")),
+            (Some("not-an-ip"), None, None),
+            (None, Some("[2001:db8::1"), None),
+            (None, None, None),
+        ];
+        for (host, addr, expected) in cases {
+            let entry = TraefikLogEntry {
+                client_host: host.map(str::to_string),
+                client_addr: addr.map(str::to_string),
+                ..Default::default()
+            };
+            let expected: Option<IpAddr> = expected.map(|ip| ip.parse().unwrap());
+            assert_eq!(entry.get_ip(), expected, "{:?} / {:?}", host, addr);
+        }
     }
 
     #[test]
@@ -87,4 +519,155 @@ mod tests {
         let entry = TraefikLogEntry::from_json_line(json).unwrap();
         assert_eq!(entry.get_path(), "/");
     }
+
+    #[test]
+    fn test_start_time_from_start_utc() {
+        let json = r#"{"ClientHost":"192.168.1.1","StartUTC":"2024-01-15T10:30:00.123456789Z"}"#;
+        let entry = TraefikLogEntry::from_json_line(json).unwrap();
+        let start = entry.start_time().unwrap();
+        assert_eq!(start.to_rfc3339(), "2024-01-15T10:30:00.123456789+00:00");
+
+        assert_eq!(entry.timestamp, Some(start));
+
+        // StartLocal is used when StartUTC is missing
+        let json = r#"{"ClientHost":"192.168.1.1","StartLocal":"2024-01-15T11:30:00+01:00"}"#;
+        let entry = TraefikLogEntry::from_json_line(json).unwrap();
+        assert_eq!(
+            entry.timestamp.unwrap().to_rfc3339(),
+            "2024-01-15T10:30:00+00:00"
+        );
+
+        // Without either (or with an invalid one), there is none
+        let json = r#"{"ClientHost":"192.168.1.1","StartUTC":"yesterday"}"#;
+        let entry = TraefikLogEntry::from_json_line(json).unwrap();
+        assert!(entry.start_time().is_none());
+        assert!(entry.timestamp.is_none());
+    }
+
+    #[test]
+    fn test_mistyped_numbers_are_ignored() {
+        let json = r#"{"ClientHost":"10.0.0.1","DownstreamStatus":"200","OriginStatus":70000,"Duration":-5,"RetryAttempts":1.5,"GzipRatio":"n/a","Overhead":{"ns":1},"RequestCount":12,"OriginDuration":null}"#;
+        let entry = TraefikLogEntry::from_json_line(json).unwrap();
+        assert_eq!(entry.get_ip(), "10.0.0.1".parse().ok());
+        assert_eq!(entry.downstream_status, None);
+        assert_eq!(entry.origin_status, None);
+        assert_eq!(entry.duration, None);
+        assert_eq!(entry.retry_attempts, None);
+        assert_eq!(entry.gzip_ratio, None);
+        assert_eq!(entry.overhead, None);
+        assert_eq!(entry.origin_duration, None);
+        assert_eq!(entry.request_count, Some(12));
+    }
+
+    /// Traefik v2.x sample, with ServiceURL as a url.URL object
+    const TRAEFIK_V2_LINE: &str = r#"{"ClientAddr":"192.168.1.10:52604","ClientHost":"192.168.1.10","ClientPort":"52604","ClientUsername":"-","DownstreamContentSize":19,"DownstreamStatus":404,"Duration":108604,"OriginContentSize":19,"OriginDuration":59100,"OriginStatus":404,"Overhead":49504,"RequestAddr":"whoami.localhost","RequestContentSize":0,"RequestCount":1,"RequestHost":"whoami.localhost","RequestMethod":"GET","RequestPath":"/notfound","RequestPort":"-","RequestProtocol":"HTTP/1.1","RequestScheme":"http","RetryAttempts":0,"RouterName":"whoami@docker","ServiceAddr":"172.18.0.3:80","ServiceName":"whoami@docker","ServiceURL":{"Scheme":"http","Opaque":"","User":null,"Host":"172.18.0.3:80","Path":"","RawPath":"","ForceQuery":false,"RawQuery":"","Fragment":"","RawFragment":""},"StartLocal":"2024-01-15T11:30:00.123456789+01:00","StartUTC":"2024-01-15T10:30:00.123456789Z","entryPointName":"web","level":"info","msg":"","time":"2024-01-15T11:30:00+01:00"}"#;
+
+    /// Traefik v3 sample over TLS, with ServiceURL as a string
+    const TRAEFIK_V3_LINE: &str = r#"{"ClientAddr":"[2001:db8::7]:41822","ClientHost":"2001:db8::7","ClientPort":"41822","ClientUsername":"alice","DownstreamContentSize":1534,"DownstreamStatus":200,"Duration":2481377,"GzipRatio":2.31,"OriginContentSize":3544,"OriginDuration":2301122,"OriginStatus":200,"Overhead":180255,"RequestAddr":"api.example.com","RequestContentSize":42,"RequestCount":9153,"RequestHost":"api.example.com","RequestMethod":"POST","RequestPath":"/v1/orders?page=2","RequestPort":"-","RequestProtocol":"HTTP/2.0","RequestScheme":"https","RetryAttempts":1,"RouterName":"api@file","ServiceAddr":"10.0.3.17:8080","ServiceName":"api-svc@file","ServiceURL":"http://10.0.3.17:8080","SpanId":"00f067aa0ba902b7","StartLocal":"2024-01-15T10:30:00.5Z","StartUTC":"2024-01-15T10:30:00.5Z","TLSCipher":"TLS_AES_128_GCM_SHA256","TLSClientSubject":"CN=client","TLSVersion":"1.3","TraceId":"4bf92f3577b34da6a3ce929d0e0e4736","entryPointName":"websecure","level":"info","msg":"","time":"2024-01-15T10:30:00Z"}"#;
+
+    #[test]
+    fn test_parse_traefik_v2_sample() {
+        let entry = TraefikLogEntry::from_json_line(TRAEFIK_V2_LINE).unwrap();
+        assert_eq!(entry.client_port.as_deref(), Some("52604"));
+        assert_eq!(entry.client_username.as_deref(), Some("-"));
+        assert_eq!(entry.request_host.as_deref(), Some("whoami.localhost"));
+        assert_eq!(entry.request_addr.as_deref(), Some("whoami.localhost"));
+        assert_eq!(entry.request_port.as_deref(), Some("-"));
+        assert_eq!(entry.request_scheme.as_deref(), Some("http"));
+        assert_eq!(entry.request_content_size, Some(0));
+        assert_eq!(entry.request_count, Some(1));
+        assert_eq!(entry.entry_point_name.as_deref(), Some("web"));
+        assert_eq!(entry.router_name.as_deref(), Some("whoami@docker"));
+        assert_eq!(entry.service_name.as_deref(), Some("whoami@docker"));
+        assert_eq!(entry.service_url.as_deref(), Some("http://172.18.0.3:80"));
+        assert_eq!(entry.service_addr.as_deref(), Some("172.18.0.3:80"));
+        assert_eq!(entry.origin_status, Some(404));
+        assert_eq!(entry.origin_content_size, Some(19));
+        assert_eq!(entry.downstream_status, Some(404));
+        assert_eq!(entry.downstream_content_size, Some(19));
+        assert_eq!(entry.duration, Some(108604));
+        assert_eq!(entry.origin_duration, Some(59100));
+        assert_eq!(entry.overhead, Some(49504));
+        assert_eq!(entry.retry_attempts, Some(0));
+        assert_eq!(
+            entry.start_local.unwrap().to_rfc3339(),
+            "2024-01-15T11:30:00.123456789+01:00"
+        );
+        assert!(entry.tls_version.is_none());
+    }
+
+    #[test]
+    fn test_parse_traefik_v3_tls_sample() {
+        let entry = TraefikLogEntry::from_json_line(TRAEFIK_V3_LINE).unwrap();
+        assert_eq!(entry.client_username.as_deref(), Some("alice"));
+        assert_eq!(entry.request_method.as_deref(), Some("POST"));
+        assert_eq!(entry.request_protocol.as_deref(), Some("HTTP/2.0"));
+        assert_eq!(entry.request_content_size, Some(42));
+        assert_eq!(entry.request_count, Some(9153));
+        assert_eq!(entry.entry_point_name.as_deref(), Some("websecure"));
+        assert_eq!(entry.service_url.as_deref(), Some("http://10.0.3.17:8080"));
+        assert_eq!(entry.retry_attempts, Some(1));
+        assert_eq!(entry.gzip_ratio, Some(2.31));
+        assert_eq!(entry.tls_version.as_deref(), Some("1.3"));
+        assert_eq!(entry.tls_cipher.as_deref(), Some("TLS_AES_128_GCM_SHA256"));
+        assert_eq!(entry.tls_client_subject.as_deref(), Some("CN=client"));
+        assert_eq!(
+            entry.trace_id.as_deref(),
+            Some("4bf92f3577b34da6a3ce929d0e0e4736")
+        );
+        assert_eq!(entry.span_id.as_deref(), Some("00f067aa0ba902b7"));
+        assert_eq!(
+            entry.start_time().unwrap().to_rfc3339(),
+            "2024-01-15T10:30:00.500+00:00"
+        );
+    }
+
+    #[test]
+    fn test_parse_logged_headers() {
+        let json = r#"{"ClientHost":"10.0.0.1","RequestPath":"/","request_User-Agent":"curl/8.0","request_X-Forwarded-For":"203.0.113.9, 10.0.0.1","request_Authorization":"REDACTED","origin_Content-Type":"text/html","downstream_Content-Type":"text/html; charset=utf-8","downstream_X-Count":null,"level":"info"}"#;
+        let entry = TraefikLogEntry::from_json_line(json).unwrap();
+        let headers = &entry.headers;
+
+        assert_eq!(headers.request.value("user-agent"), Some("curl/8.0"));
+        assert_eq!(
+            headers.request.value("X-FORWARDED-FOR"),
+            Some("203.0.113.9, 10.0.0.1")
+        );
+        assert!(headers.request.is_redacted("Authorization"));
+        assert_eq!(headers.request.len(), 3);
+        assert_eq!(headers.origin.value("content-type"), Some("text/html"));
+        assert_eq!(
+            headers.downstream.value("Content-Type"),
+            Some("text/html; charset=utf-8")
+        );
+        assert_eq!(headers.downstream.len(), 1);
+
+        // Regular fields are not mistaken for headers
+        let entry = TraefikLogEntry::from_json_line(TRAEFIK_V2_LINE).unwrap();
+        assert!(entry.headers.request.is_empty());
+        assert_eq!(entry.request_host.as_deref(), Some("whoami.localhost"));
+    }
+
+    #[test]
+    fn test_borrowed_entry_matches_owned() {
+        for line in [TRAEFIK_V2_LINE, TRAEFIK_V3_LINE] {
+            let owned = TraefikLogEntry::from_json_line(line).unwrap();
+            let borrowed = BorrowedEntry::from_json_line(line).unwrap();
+            assert_eq!(borrowed.get_ip(), owned.get_ip());
+            assert_eq!(borrowed.get_path(), owned.get_path());
+            assert_eq!(borrowed.start_time(), owned.start_time());
+            assert!(matches!(borrowed.request_path, Some(Cow::Borrowed(_))));
+        }
+
+        // Escaped strings are decoded into an owned copy
+        let line = r#"{"ClientHost":"10.0.0.1","RequestPath":"\/café","request_X-Forwarded-For":"203.0.113.9"}"#;
+        let borrowed = BorrowedEntry::from_json_line(line).unwrap();
+        assert_eq!(borrowed.get_path(), "/café");
+        assert!(matches!(borrowed.request_path, Some(Cow::Owned(_))));
+        assert_eq!(borrowed.forwarded_for.as_deref(), Some("203.0.113.9"));
+        assert!(borrowed.start_time().is_none());
+
+        assert!(BorrowedEntry::from_json_line("not json").is_err());
+        assert!(BorrowedEntry::from_json_line(r#"{"RequestPath":7}"#).is_err());
+    }
 }
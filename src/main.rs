@@ -1,129 +1,530 @@
+mod archive_reader;
+mod batch;
+mod checkpoint;
+mod clf;
+mod client_ip;
+mod config;
+mod dir_watcher;
 mod display;
+mod envelope;
+mod event;
 mod file_reader;
+mod file_watcher;
+mod format;
+mod headers;
 mod log_entry;
+mod path_normalizer;
+mod pipeline;
+mod query_stats;
+mod replay;
 mod statistics;
+mod stdin_reader;
+mod syslog;
+#[cfg(unix)]
+mod unix_input;
 
 use anyhow::{Context, Result};
-use std::io::{self, BufRead};
-use std::sync::mpsc;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use archive_reader::{ArchiveReader, Compression, rotation_set, sort_oldest_first};
+use batch::BatchAnalyzer;
+use checkpoint::Checkpoint;
+use client_ip::IpResolver;
+use config::{Config, USAGE};
+use dir_watcher::DirectoryWatcher;
 use display::DisplayFormatter;
-use file_reader::LogTailer;
-use log_entry::TraefikLogEntry;
+use event::MonitorEvent;
+use file_reader::{LineBuffer, LogTailer, StartPosition, expand_paths};
+use file_watcher::FileWatcher;
+use format::format_by_name;
+use path_normalizer::{PathNormalizer, PathRule};
+use pipeline::Pipeline;
+use replay::spawn_replay;
 use statistics::StatsCollector;
+use stdin_reader::{STDIN_SOURCE, open_terminal, spawn_line_reader};
+use syslog::spawn_syslog_listener;
+#[cfg(unix)]
+use unix_input::{spawn_fifo_reader, spawn_socket_listener};
 
-const LOG_FILE_PATH: &str = "access.log";
-const POLL_INTERVAL_SECS: u64 = 3;
-const DEFAULT_TOP_N: usize = 10;
+/// Poll interval when no filesystem notifications are available
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Safety-net poll interval while notifications are active, in case an event is missed
+const WATCHED_POLL_INTERVAL: Duration = Duration::from_secs(10);
+const ARCHIVE_BATCH_LINES: usize = 10_000;
+/// Most queued events handled before the files are read and the screen is redrawn
+/// File change notifications do not count, they only mark the files for reading
+const MAX_EVENTS_PER_CYCLE: usize = 64;
+/// Most events waiting for the monitoring loop; once full, senders block, so an input
+/// faster than parsing is slowed down instead of queueing lines without bound
+const EVENT_QUEUE_LEN: usize = 64;
 
 fn main() -> Result<()> {
+    let config = Config::from_args(std::env::args().skip(1))
+        .map_err(|e| anyhow::anyhow!("{}\n\n{}", e, USAGE))?;
+    if config.show_help {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    if !cfg!(unix) && (config.fifo.is_some() || config.socket.is_some()) {
+        anyhow::bail!("--fifo and --socket are only supported on Unix");
+    }
+    if config.analyze {
+        return analyze(&config);
+    }
+
     // Display initial message
     println!("Starting Traefik Access Log Monitor...");
-    println!("Looking for: {}", LOG_FILE_PATH);
+    if config.read_stdin {
+        println!("Reading log lines from stdin");
+    }
+    if let Some(ref replay) = config.replay {
+        println!(
+            "Replaying {} at {}x speed",
+            replay.display(),
+            config.replay_speed
+        );
+    }
+    if !config.paths.is_empty() {
+        println!("Looking for: {}", config.paths.join(", "));
+    }
     println!();
 
-    // Initialize file tailer
-    let mut tailer = LogTailer::new(LOG_FILE_PATH)
-        .context(format!("Failed to open log file: {}", LOG_FILE_PATH))?;
+    // Initialize statistics collector and the line parsing pipeline
+    let mut stats = StatsCollector::with_resolver(IpResolver::new(config.trusted_proxies.clone()))
+        .with_query_limits(config.query_limits);
+    if let Some(normalizer) = path_normalizer(&config)? {
+        stats = stats.with_normalizer(normalizer);
+    }
+    let format = config.format.as_deref().map(format_by_name).transpose()?;
+    let mut pipeline = Pipeline::new(config.envelope, format);
+
+    // Compressed inputs are read once, everything else is tailed
+    let paths = expand_paths(&config.paths)?;
+    let (mut archives, live_paths): (Vec<_>, Vec<_>) = paths
+        .into_iter()
+        .partition(|path| Compression::from_path(path) != Compression::None);
+
+    // With --rotated, a live file's own history is read first, so backfill it fully
+    let start = if config.include_rotated && config.start == StartPosition::End {
+        StartPosition::Beginning
+    } else {
+        config.start
+    };
+
+    // Load saved offsets so a restart resumes where the last run stopped
+    let mut checkpoint = match config.state_file {
+        Some(ref state_file) => Some(Checkpoint::load(state_file)?),
+        None => None,
+    };
+
+    // Read archives given directly, then each live file's rotation set, oldest first.
+    // Direct archives are recorded in the checkpoint; a live file resuming from it has
+    // had its rotation set read by an earlier run already
+    sort_oldest_first(&mut archives);
+    for path in &archives {
+        let source = path.display().to_string();
+        read_archive(
+            &mut pipeline,
+            &mut stats,
+            checkpoint.as_mut(),
+            path,
+            &source,
+            start,
+        )?;
+    }
+    if config.include_rotated {
+        for live_path in &live_paths {
+            if checkpoint
+                .as_ref()
+                .is_some_and(|c| c.get(live_path).is_some())
+            {
+                println!("✓ Skipping archives read before: {}", live_path.display());
+                continue;
+            }
+            let source = live_path.display().to_string();
+            for path in rotation_set(live_path)? {
+                read_archive(&mut pipeline, &mut stats, None, &path, &source, start)?;
+            }
+        }
+    }
+
+    // Initialize one file tailer per log file
+    let mut tailers = Vec::new();
+    for path in &live_paths {
+        let saved = checkpoint.as_ref().and_then(|c| c.get(path));
+        let tailer = LogTailer::resume(path, saved, start)
+            .context(format!("Failed to open log file: {}", path.display()))?;
+        println!("✓ Successfully opened log file: {}", path.display());
+        // Not when the file was rotated or truncated since and is read from the start
+        if saved.is_some() && tailer.state() == saved {
+            println!("✓ Resuming from checkpoint: {}", path.display());
+        }
+        tailers.push(tailer);
+    }
+    if !tailers.is_empty() {
+        println!("✓ Started {}, then monitoring for new requests", start);
+    }
+
+    // Tail every matching file in the watched directory, including ones created later
+    let mut dir_watcher = match config.watch_dir {
+        Some(ref dir) => {
+            let watcher = DirectoryWatcher::new(
+                dir,
+                &config.watch_pattern,
+                config.idle_timeout,
+                start,
+                |path| checkpoint.as_ref().and_then(|c| c.get(path)),
+            )?;
+            println!(
+                "✓ Watching directory {} for {} ({} files)",
+                dir.display(),
+                config.watch_pattern,
+                watcher.tailers().count()
+            );
+            Some(watcher)
+        }
+        None => None,
+    };
+    let has_files = !tailers.is_empty() || dir_watcher.is_some();
+
+    // Readers, the file watcher and the command thread all feed one bounded event channel
+    let (tx, rx) = mpsc::sync_channel(EVENT_QUEUE_LEN);
 
-    println!("✓ Successfully opened log file");
-    println!("✓ Ignoring existing entries, monitoring for new requests only");
+    // Wake up as soon as a tailed file changes, or fall back to polling
+    let watcher = if !has_files || config.force_poll {
+        None
+    } else {
+        let paths: Vec<_> = tailers.iter().map(|t| t.path().to_path_buf()).collect();
+        let directory = dir_watcher.as_ref().map(|w| (w.dir(), w.pattern()));
+        match FileWatcher::new(&paths, directory, tx.clone()) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                eprintln!(
+                    "Warning: File notifications unavailable, polling instead: {:#}",
+                    e
+                );
+                None
+            }
+        }
+    };
+    let poll_interval = if watcher.is_some() {
+        WATCHED_POLL_INTERVAL
+    } else {
+        POLL_INTERVAL
+    };
+
+    // Forward piped log lines from stdin
+    if config.read_stdin {
+        spawn_line_reader(io::stdin(), STDIN_SOURCE, tx.clone());
+    }
+
+    // Syslog senders are tagged by host
+    if let Some(ref addr) = config.syslog {
+        let addr = spawn_syslog_listener(addr, tx.clone())?;
+        println!("✓ Listening for syslog messages on {} (UDP and TCP)", addr);
+    }
+
+    // Named pipes and Unix sockets feed the loop the same way
+    #[cfg(unix)]
+    {
+        if let Some(ref fifo) = config.fifo {
+            spawn_fifo_reader(fifo, tx.clone())?;
+            println!("✓ Reading log lines from FIFO: {}", fifo.display());
+        }
+        if let Some(ref socket) = config.socket {
+            spawn_socket_listener(socket, tx.clone())?;
+            println!("✓ Listening for log lines on socket: {}", socket.display());
+        }
+    }
+
+    // Runtime commands come from stdin, or from the terminal when stdin carries log lines
+    let command_input: Option<Box<dyn BufRead + Send>> = if config.read_stdin {
+        match open_terminal() {
+            Ok(terminal) => Some(terminal),
+            Err(e) => {
+                eprintln!("Warning: No terminal for runtime commands: {}", e);
+                None
+            }
+        }
+    } else {
+        Some(Box::new(BufReader::new(io::stdin())))
+    };
     println!();
-    println!(
-        "Starting monitoring loop (polling every {} seconds)...",
-        POLL_INTERVAL_SECS
-    );
+    if watcher.is_some() {
+        println!("Starting monitoring loop (watching files for changes)...");
+    } else if has_files {
+        println!(
+            "Starting monitoring loop (polling every {} ms)...",
+            poll_interval.as_millis()
+        );
+    } else {
+        println!("Starting monitoring loop...");
+    }
     println!();
 
     // Wait a moment for user to see the startup messages
     thread::sleep(Duration::from_secs(2));
 
-    // Initialize statistics collector
-    let mut stats = StatsCollector::new();
-
-    // Set up channel for receiving top_n updates from input thread
-    let (tx, rx) = mpsc::channel();
-    let mut top_n = DEFAULT_TOP_N;
+    let mut top_n = config.top_n;
 
     // Spawn input handling thread
-    thread::spawn(move || {
-        let stdin = io::stdin();
-        let reader = stdin.lock();
-
-        for input in reader.lines().map_while(Result::ok) {
-            let trimmed = input.trim();
-            if let Ok(new_top_n) = trimmed.parse::<usize>()
-                && new_top_n > 0
-                && new_top_n <= 100
-            {
-                // Send the new top_n value to the main thread
-                if tx.send(new_top_n).is_err() {
-                    break; // Main thread has terminated
+    if let Some(reader) = command_input {
+        let tx = tx.clone();
+        thread::spawn(move || {
+            for input in reader.lines().map_while(Result::ok) {
+                let trimmed = input.trim();
+                if let Ok(new_top_n) = trimmed.parse::<usize>()
+                    && new_top_n > 0
+                    && new_top_n <= 100
+                {
+                    // Send the new top_n value to the main thread
+                    if tx.send(MonitorEvent::TopN(new_top_n)).is_err() {
+                        break; // Main thread has terminated
+                    }
                 }
             }
-        }
-    });
+        });
+    }
 
-    // Display initial empty state
+    // Replayed lines arrive like live ones, paced by their timestamps, starting once the
+    // startup messages have been shown
+    if let Some(ref replay) = config.replay {
+        spawn_replay(replay, config.replay_speed, config.envelope, tx.clone())?;
+    }
+
+    // Display initial state, including anything backfilled above
     DisplayFormatter::display_stats(&stats, top_n);
 
+    // Reading and drawing run on separate schedules: new data is read as soon as it is
+    // signalled, while redraws are throttled to the refresh interval
+    let refresh_interval = config.refresh_interval;
+    let mut last_draw = Instant::now();
+    let mut dirty = false;
+    let mut next_poll = Instant::now();
+    // Reused by every tailer, so steady-state reads do not allocate per line
+    let mut lines = LineBuffer::default();
+
     // Main monitoring loop
     loop {
-        // Check for top_n updates from input thread (non-blocking)
-        while let Ok(new_top_n) = rx.try_recv() {
-            top_n = new_top_n;
-            // Immediately update display with new top_n
-            DisplayFormatter::display_stats(&stats, top_n);
+        // Sleep until the next event, poll or pending redraw
+        let mut deadline = has_files.then_some(next_poll);
+        if dirty {
+            let redraw_at = last_draw + refresh_interval;
+            deadline = Some(deadline.map_or(redraw_at, |poll_at| poll_at.min(redraw_at)));
         }
+        let first_event = match deadline {
+            Some(deadline) => {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                match rx.recv_timeout(timeout) {
+                    Ok(event) => Some(event),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => unreachable!("monitor holds a sender"),
+                }
+            }
+            None => Some(rx.recv().expect("monitor holds a sender")),
+        };
 
-        // Sleep first (poll interval)
-        thread::sleep(Duration::from_secs(POLL_INTERVAL_SECS));
+        // Handle what has queued up, so bursts of notifications result in a single
+        // read of the files. Bounded, so a busy stream cannot hold off the redraw
+        let mut _parsed_count = 0;
+        let mut _error_count = 0;
+        let mut files_changed = false;
+        let mut handled = 0;
 
-        // Read new lines from the log file
-        match tailer.read_new_lines() {
-            Ok(lines) => {
-                if lines.is_empty() {
-                    // No new data, but still refresh display
-                    DisplayFormatter::display_stats(&stats, top_n);
+        for event in first_event.into_iter().chain(rx.try_iter()) {
+            match event {
+                MonitorEvent::FilesChanged => {
+                    // Further changes send a new event, the read below covers this one
+                    if let Some(ref watcher) = watcher {
+                        watcher.rearm();
+                    }
+                    files_changed = true;
                     continue;
                 }
+                MonitorEvent::Lines {
+                    source,
+                    lines,
+                    invalid_utf8,
+                } => {
+                    stats.record_invalid_utf8(invalid_utf8);
+                    let (parsed, errors) = pipeline.ingest_lines(&mut stats, &source, &lines);
+                    _parsed_count += parsed;
+                    _error_count += errors;
+                    dirty = true;
+                }
+                MonitorEvent::SourceClosed(source) => pipeline.close_source(&source),
+                MonitorEvent::TopN(new_top_n) => {
+                    top_n = new_top_n;
+                    // Immediately update display with new top_n
+                    DisplayFormatter::display_stats(&stats, top_n);
+                    last_draw = Instant::now();
+                    dirty = false;
+                }
+            }
+            handled += 1;
+            if handled == MAX_EVENTS_PER_CYCLE {
+                break;
+            }
+        }
 
-                // Process each new line
-                let mut _parsed_count = 0;
-                let mut _error_count = 0;
-
-                for line in lines {
-                    match TraefikLogEntry::from_json_line(&line) {
-                        Ok(entry) => {
-                            stats.add_entry(&entry);
-                            _parsed_count += 1;
-                        }
-                        Err(e) => {
-                            // Skip malformed JSON entries
-                            _error_count += 1;
-                            eprintln!("Warning: Failed to parse log entry: {}", e);
-                            eprintln!("Line: {}", line);
-                        }
-                    }
+        // Read new lines from every log file when notified or when the poll is due
+        if has_files && (files_changed || Instant::now() >= next_poll) {
+            for tailer in tailers.iter_mut() {
+                lines.clear();
+                if let Err(e) = tailer.read_new_lines(&mut lines) {
+                    // Handle file read errors, the next poll retries; lines read before
+                    // the error are still counted below
+                    eprintln!("Error reading log file {}: {}", tailer.path().display(), e);
+                }
+                stats.record_invalid_utf8(tailer.take_invalid_utf8());
+                if lines.is_empty() {
+                    continue;
                 }
 
-                // Update display with new statistics
-                DisplayFormatter::display_stats(&stats, top_n);
+                // Tag entries with the file they came from
+                let source = tailer.path().display().to_string();
+                let (parsed, errors) = pipeline.ingest_lines(&mut stats, &source, lines.lines());
+                _parsed_count += parsed;
+                _error_count += errors;
+                dirty = true;
+            }
 
-                // Optionally show parse stats in debug mode
-                #[cfg(debug_assertions)]
-                if _error_count > 0 {
-                    eprintln!("Parsed {} entries, {} errors", _parsed_count, _error_count);
+            // Files in the watched directory, including newly created ones
+            if let Some(ref mut dir_watcher) = dir_watcher {
+                let batches = dir_watcher.read_new_lines().unwrap_or_else(|e| {
+                    eprintln!("Error scanning watched directory: {:#}", e);
+                    Vec::new()
+                });
+                for batch in batches {
+                    stats.record_invalid_utf8(batch.invalid_utf8);
+                    let (parsed, errors) =
+                        pipeline.ingest_lines(&mut stats, &batch.source, batch.lines);
+                    _parsed_count += parsed;
+                    _error_count += errors;
+                    dirty = true;
                 }
             }
-            Err(e) => {
-                // Handle file read errors
-                eprintln!("Error reading log file: {}", e);
-                eprintln!("Retrying in {} seconds...", POLL_INTERVAL_SECS);
-                DisplayFormatter::display_stats(&stats, top_n);
+            next_poll = Instant::now() + poll_interval;
+
+            // Persist offsets after every read cycle
+            if let Some(ref mut checkpoint) = checkpoint {
+                let dir_tailers = dir_watcher.iter().flat_map(|w| w.tailers());
+                save_checkpoint(checkpoint, tailers.iter().chain(dir_tailers));
             }
         }
+
+        // Update display with new statistics, at most once per refresh interval
+        if dirty && last_draw.elapsed() >= refresh_interval {
+            DisplayFormatter::display_stats(&stats, top_n);
+            last_draw = Instant::now();
+            dirty = false;
+        }
+
+        // Optionally show parse stats in debug mode
+        #[cfg(debug_assertions)]
+        if _error_count > 0 {
+            eprintln!("Parsed {} entries, {} errors", _parsed_count, _error_count);
+        }
     }
 }
+
+/// Record the tailers' current offsets and write the checkpoint if anything moved
+fn save_checkpoint<'a>(
+    checkpoint: &mut Checkpoint,
+    tailers: impl IntoIterator<Item = &'a LogTailer>,
+) {
+    let mut changed = false;
+    for tailer in tailers {
+        if let Some(state) = tailer.state() {
+            changed |= checkpoint.update(tailer.path(), state);
+        }
+    }
+
+    if changed && let Err(e) = checkpoint.save() {
+        eprintln!("Warning: Failed to save checkpoint: {:#}", e);
+    }
+}
+
+/// Stream the lines of a rotated archive selected by `start` into the statistics
+/// With a checkpoint, an archive it records as read is skipped, and one read now is added
+fn read_archive(
+    pipeline: &mut Pipeline,
+    stats: &mut StatsCollector,
+    checkpoint: Option<&mut Checkpoint>,
+    path: &Path,
+    source: &str,
+    start: StartPosition,
+) -> Result<()> {
+    let mut reader = ArchiveReader::with_start(path, start)?;
+    let state = reader.state();
+    if let Some(ref checkpoint) = checkpoint
+        && state.is_some()
+        && checkpoint.get(path) == state
+    {
+        println!("✓ Skipping archive read before: {}", path.display());
+        return Ok(());
+    }
+
+    let mut parsed_total = 0;
+    loop {
+        let lines = reader.read_lines(ARCHIVE_BATCH_LINES)?;
+        if lines.is_empty() {
+            break;
+        }
+        stats.record_invalid_utf8(reader.take_invalid_utf8());
+        let (parsed, _) = pipeline.ingest_lines(stats, source, &lines);
+        parsed_total += parsed;
+    }
+
+    println!(
+        "✓ Read {} entries from archive: {}",
+        parsed_total,
+        reader.path().display()
+    );
+
+    if let Some(checkpoint) = checkpoint
+        && let Some(state) = state
+        && checkpoint.update(path, state)
+        && let Err(e) = checkpoint.save()
+    {
+        eprintln!("Warning: Failed to save checkpoint: {:#}", e);
+    }
+    Ok(())
+}
+
+/// Path normalization configured by the options (None = count paths as logged)
+fn path_normalizer(config: &Config) -> Result<Option<PathNormalizer>> {
+    if config.raw_paths {
+        return Ok(None);
+    }
+    let rules = match config.path_rules {
+        Some(ref path) => PathRule::load(path)?,
+        None => Vec::new(),
+    };
+    Ok(Some(PathNormalizer::new(config.keep_query, rules)))
+}
+
+/// Analyze the given files once on all cores, print the statistics and throughput
+fn analyze(config: &Config) -> Result<()> {
+    if config.read_stdin || config.watch_dir.is_some() {
+        anyhow::bail!("--analyze works on files only, not stdin or --watch-dir");
+    }
+
+    let paths = expand_paths(&config.paths)?;
+    let format = config.format.as_deref().map(format_by_name).transpose()?;
+    let mut analyzer = BatchAnalyzer::new(config.threads, config.envelope, format)
+        .resolver(IpResolver::new(config.trusted_proxies.clone()))
+        .query_limits(config.query_limits);
+    if let Some(normalizer) = path_normalizer(config)? {
+        analyzer = analyzer.normalizer(normalizer);
+    }
+    let (stats, report) = analyzer.analyze(&paths)?;
+
+    println!("{}", DisplayFormatter::render_report(&stats, config.top_n));
+    println!("{}", report.render());
+    Ok(())
+}
@@ -0,0 +1,223 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::fs;
+use std::path::Path;
+
+/// Minimum length of a hex segment to be treated as a hash
+const MIN_HASH_LEN: usize = 16;
+
+/// A user-defined rewrite from the rules file
+/// Every match of `pattern` is replaced with `template`, which may refer to capture
+/// groups as `$1` or `$name`
+#[derive(Debug, Clone)]
+pub struct PathRule {
+    regex: Regex,
+    template: String,
+}
+
+/// Rule as written in the rules file
+#[derive(Deserialize)]
+struct RuleSpec {
+    pattern: String,
+    template: String,
+}
+
+impl PathRule {
+    /// Compile a rule
+    pub fn new(pattern: &str, template: &str) -> Result<Self> {
+        let regex = Regex::new(pattern).context(format!("Invalid path pattern: {}", pattern))?;
+        Ok(PathRule {
+            regex,
+            template: template.to_string(),
+        })
+    }
+
+    /// Load rules from a JSON file: `[{"pattern": "...", "template": "..."}, ...]`
+    pub fn load(path: &Path) -> Result<Vec<Self>> {
+        let content = fs::read_to_string(path)
+            .context(format!("Failed to read path rules: {}", path.display()))?;
+        let specs: Vec<RuleSpec> = serde_json::from_str(&content)
+            .context(format!("Failed to parse path rules: {}", path.display()))?;
+        specs
+            .iter()
+            .map(|spec| PathRule::new(&spec.pattern, &spec.template))
+            .collect()
+    }
+}
+
+/// Turns request paths into route templates, so requests to the same route are
+/// counted together: `/api/users/123?x=1` and `/api/users/456` both become
+/// `/api/users/{id}`
+/// Steps: the query string is stripped (unless kept), the path is percent-decoded,
+/// the user's rules are applied in order, and remaining segments that are numeric IDs,
+/// UUIDs or hex hashes become `{id}`, `{uuid}` and `{hash}`
+#[derive(Debug, Clone, Default)]
+pub struct PathNormalizer {
+    keep_query: bool,
+    rules: Vec<PathRule>,
+}
+
+impl PathNormalizer {
+    /// Create a normalizer applying `rules` before the built-in placeholders
+    pub fn new(keep_query: bool, rules: Vec<PathRule>) -> Self {
+        PathNormalizer { keep_query, rules }
+    }
+
+    /// Template of a request path; only allocates when the path changes
+    pub fn normalize<'a>(&self, path: &'a str) -> Cow<'a, str> {
+        let (path, query) = match path.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (path, None),
+        };
+
+        let mut template = percent_decode(path);
+        for rule in &self.rules {
+            if rule.regex.is_match(&template) {
+                let replaced = rule.regex.replace_all(&template, rule.template.as_str());
+                template = Cow::Owned(replaced.into_owned());
+            }
+        }
+        if template
+            .split('/')
+            .any(|segment| placeholder(segment).is_some())
+        {
+            let segments: Vec<&str> = template
+                .split('/')
+                .map(|segment| placeholder(segment).unwrap_or(segment))
+                .collect();
+            template = Cow::Owned(segments.join("/"));
+        }
+
+        match query {
+            Some(query) if self.keep_query => Cow::Owned(format!("{}?{}", template, query)),
+            _ => template,
+        }
+    }
+}
+
+/// Placeholder for a path segment that identifies a single resource
+fn placeholder(segment: &str) -> Option<&'static str> {
+    if segment.is_empty() {
+        None
+    } else if segment.bytes().all(|b| b.is_ascii_digit()) {
+        Some("{id}")
+    } else if is_uuid(segment) {
+        Some("{uuid}")
+    } else if segment.len() >= MIN_HASH_LEN
+        && segment.bytes().all(|b| b.is_ascii_hexdigit())
+        && segment.bytes().any(|b| b.is_ascii_digit())
+    {
+        Some("{hash}")
+    } else {
+        None
+    }
+}
+
+/// Whether a segment is a UUID in its 8-4-4-4-12 hex form
+fn is_uuid(segment: &str) -> bool {
+    let groups: Vec<&str> = segment.split('-').collect();
+    groups.len() == 5
+        && groups
+            .iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(group, len)| group.len() == len && group.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+/// Decode `%XX` escapes; invalid escapes are kept as written and invalid UTF-8 is
+/// replaced. Only allocates when there is something to decode
+pub fn percent_decode(path: &str) -> Cow<'_, str> {
+    if !path.contains('%') {
+        return Cow::Borrowed(path);
+    }
+
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    Cow::Owned(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_builtin_placeholders() {
+        let normalizer = PathNormalizer::default();
+        let cases = [
+            ("/api/users/123?x=1", "/api/users/{id}"),
+            ("/api/users/456", "/api/users/{id}"),
+            ("/", "/"),
+            ("/api/users", "/api/users"),
+            (
+                "/orders/550e8400-e29b-41d4-a716-446655440000/items/7",
+                "/orders/{uuid}/items/{id}",
+            ),
+            ("/blobs/9f86d081884c7d659a2feaa0c55ad015", "/blobs/{hash}"),
+            ("/v2/deadbeef", "/v2/deadbeef"),
+            ("/caf%C3%A9/%7Euser/100%", "/café/~user/100%"),
+            ("/files/%31%32", "/files/{id}"),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(normalizer.normalize(path), expected, "{}", path);
+        }
+        assert!(matches!(
+            normalizer.normalize("/static/app.js"),
+            Cow::Borrowed(_)
+        ));
+
+        let keep_query = PathNormalizer::new(true, Vec::new());
+        assert_eq!(
+            keep_query.normalize("/api/users/123?x=1"),
+            "/api/users/{id}?x=1"
+        );
+    }
+
+    #[test]
+    fn test_normalize_with_rules() {
+        let test_file = "test_path_rules.json";
+        fs::write(
+            test_file,
+            r#"[
+                {"pattern": "^/u/[^/]+", "template": "/u/{user}"},
+                {"pattern": "^/static/.*$", "template": "/static/*"},
+                {"pattern": "^/v(\\d+)/", "template": "/{version}/"}
+            ]"#,
+        )
+        .unwrap();
+        let rules = PathRule::load(Path::new(test_file)).unwrap();
+        let normalizer = PathNormalizer::new(false, rules);
+
+        assert_eq!(
+            normalizer.normalize("/u/alice/posts/12"),
+            "/u/{user}/posts/{id}"
+        );
+        assert_eq!(normalizer.normalize("/static/js/app.123.js"), "/static/*");
+        assert_eq!(normalizer.normalize("/v2/items"), "/{version}/items");
+        assert_eq!(normalizer.normalize("/other/7"), "/other/{id}");
+
+        fs::write(test_file, r#"[{"pattern": "([", "template": ""}]"#).unwrap();
+        assert!(PathRule::load(Path::new(test_file)).is_err());
+
+        // Clean up
+        fs::remove_file(test_file).ok();
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::envelope::{Envelope, EnvelopeDecoder};
+use crate::format::{FORMATS, FormatDetector, LogFormat};
+use crate::statistics::StatsCollector;
+
+/// Time after an input's first line after which its format is decided from the lines
+/// seen so far, for inputs that send fewer lines than the detection sample
+const DETECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Decoding state of one input
+struct SourceState {
+    decoder: EnvelopeDecoder,
+    /// Format of the input, once known
+    format: Option<&'static dyn LogFormat>,
+    /// Votes of the lines seen while the format is not known
+    detector: FormatDetector,
+    /// When the current detection sample started
+    detect_started: Instant,
+}
+
+impl SourceState {
+    fn new(envelope: Envelope) -> Self {
+        SourceState {
+            decoder: EnvelopeDecoder::new(envelope),
+            format: None,
+            detector: FormatDetector::default(),
+            detect_started: Instant::now(),
+        }
+    }
+
+    /// Format to parse a line with, while detecting the input's format
+    /// Until the sample is complete (or has timed out) each line is parsed as the
+    /// format it matches; a sample matching no format is started again
+    fn detect(&mut self, line: &str) -> &'static dyn LogFormat {
+        let matched = self.detector.observe(line);
+        if self.detector.is_complete() || self.detect_started.elapsed() >= DETECT_TIMEOUT {
+            self.format = self.detector.decide();
+            self.detector = FormatDetector::default();
+            self.detect_started = Instant::now();
+        }
+        matched.unwrap_or(FORMATS[0])
+    }
+}
+
+/// Turns raw input lines into log entries and feeds them to the statistics
+/// Keeps per-source decoding state, so split container log records from one input
+/// are never joined with records from another, and each input's format is detected
+/// separately from its first `DETECT_SAMPLE_LINES` lines
+pub struct Pipeline {
+    envelope: Envelope,
+    format: Option<&'static dyn LogFormat>,
+    sources: HashMap<String, SourceState>,
+    warn_malformed: bool,
+}
+
+impl Pipeline {
+    /// Create a pipeline that unwraps the given container log envelope and parses
+    /// lines in the given format (None = detect per input)
+    pub fn new(envelope: Envelope, format: Option<&'static dyn LogFormat>) -> Self {
+        Pipeline {
+            envelope,
+            format,
+            sources: HashMap::new(),
+            warn_malformed: true,
+        }
+    }
+
+    /// Stop printing a warning for every malformed line (they are still counted)
+    /// Used for bulk analysis, where a bad log would otherwise flood stderr
+    pub fn quiet(mut self) -> Self {
+        self.warn_malformed = false;
+        self
+    }
+
+    /// Parse lines and add them to the statistics, tagged with their source
+    /// Returns the number of parsed entries and the number of malformed lines
+    /// Lines are only borrowed, so callers can reuse their buffers
+    pub fn ingest_lines<S: AsRef<str>>(
+        &mut self,
+        stats: &mut StatsCollector,
+        source: &str,
+        lines: &[S],
+    ) -> (usize, usize) {
+        let mut counts = (0, 0);
+
+        // The key is only copied the first time a source is seen
+        if !self.sources.contains_key(source) {
+            let state = SourceState::new(self.envelope);
+            self.sources.insert(source.to_string(), state);
+        }
+        let state = self
+            .sources
+            .get_mut(source)
+            .expect("source state was just inserted");
+
+        for line in lines {
+            // Unwrap container log envelopes, waiting for the rest of split records
+            let Some(line) = state.decoder.decode(line.as_ref()) else {
+                continue;
+            };
+            let format = match self.format.or(state.format) {
+                Some(format) => format,
+                None => state.detect(&line),
+            };
+            Self::add_line(
+                format,
+                &line,
+                source,
+                stats,
+                self.warn_malformed,
+                &mut counts,
+            );
+        }
+
+        counts
+    }
+
+    /// Drop the decoding state of a source that has ended
+    /// A record it left unfinished is discarded; if the source comes back, its format
+    /// is detected again
+    pub fn close_source(&mut self, source: &str) {
+        self.sources.remove(source);
+    }
+
+    /// Parse one unwrapped line and add it to the statistics, counting it in
+    /// `counts` as parsed or malformed
+    fn add_line(
+        format: &dyn LogFormat,
+        line: &str,
+        source: &str,
+        stats: &mut StatsCollector,
+        warn_malformed: bool,
+        counts: &mut (usize, usize),
+    ) {
+        match format.add_line(line, source, stats) {
+            Ok(()) => counts.0 += 1,
+            Err(e) => {
+                // Skip malformed entries
+                counts.1 += 1;
+                stats.record_malformed();
+                if warn_malformed {
+                    eprintln!("Warning: Failed to parse log entry: {}", e);
+                    eprintln!("Line: {}", line);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::DETECT_SAMPLE_LINES;
+
+    #[test]
+    fn test_pipeline_unwraps_envelopes_per_source() {
+        let mut stats = StatsCollector::new();
+        let mut pipeline = Pipeline::new(Envelope::Auto, None);
+
+        let lines = vec![
+            r#"{"log":"{\"ClientHost\":\"10.0.0.1\"}\n","stream":"stdout","time":"2024-01-01T00:00:00Z"}"#.to_string(),
+            r#"2024-01-01T00:00:00Z stdout F {"ClientHost":"10.0.0.2"}"#.to_string(),
+            r#"{"ClientHost":"10.0.0.3"}"#.to_string(),
+            "not json".to_string(),
+        ];
+        assert_eq!(pipeline.ingest_lines(&mut stats, "pod.log", &lines), (3, 1));
+
+        // A partial record from one source is not completed by another source
+        pipeline.ingest_lines(
+            &mut stats,
+            "a.log",
+            &[r#"2024-01-01T00:00:00Z stdout P {"ClientHost":"#],
+        );
+        pipeline.ingest_lines(
+            &mut stats,
+            "b.log",
+            &[r#"2024-01-01T00:00:00Z stdout F {"ClientHost":"10.0.0.9"}"#],
+        );
+        pipeline.ingest_lines(
+            &mut stats,
+            "a.log",
+            &[r#"2024-01-01T00:00:00Z stdout F "10.0.0.4"}"#],
+        );
+
+        assert_eq!(stats.total_requests(), 5);
+        assert_eq!(stats.unique_ips(), 5);
+    }
+
+    #[test]
+    fn test_closed_source_starts_over() {
+        let mut stats = StatsCollector::new();
+        let mut pipeline = Pipeline::new(Envelope::Auto, None);
+
+        pipeline.ingest_lines(
+            &mut stats,
+            "sock#2",
+            &[r#"2024-01-01T00:00:00Z stdout P {"ClientHost":"#],
+        );
+        pipeline.close_source("sock#2");
+        assert!(pipeline.sources.is_empty());
+
+        // A later connection under the same tag does not complete the old record
+        assert_eq!(
+            pipeline.ingest_lines(
+                &mut stats,
+                "sock#2",
+                &[r#"2024-01-01T00:00:00Z stdout F "10.0.0.4"}"#],
+            ),
+            (0, 1)
+        );
+    }
+
+    #[test]
+    fn test_pipeline_detects_format_per_source() {
+        let mut stats = StatsCollector::new();
+        let mut pipeline = Pipeline::new(Envelope::Auto, None);
+
+        let clf = r#"10.0.0.1 - - [15/Jan/2024:10:30:00 +0000] "GET / HTTP/1.1" 200 5 "-" "-" 1 "r@file" "http://b:80" 1ms"#;
+        let lines = vec![clf.to_string(), "garbage".to_string(), clf.to_string()];
+        assert_eq!(pipeline.ingest_lines(&mut stats, "clf.log", &lines), (2, 1));
+
+        let json = r#"{"ClientHost":"10.0.0.2"}"#.to_string();
+        assert_eq!(
+            pipeline.ingest_lines(&mut stats, "json.log", &[&json]),
+            (1, 0)
+        );
+
+        // Once the sample is complete the detected format sticks: JSON in the CLF
+        // input is malformed
+        let sample = vec![clf; DETECT_SAMPLE_LINES];
+        pipeline.ingest_lines(&mut stats, "clf.log", &sample);
+        assert_eq!(
+            pipeline.ingest_lines(&mut stats, "clf.log", &[json]),
+            (0, 1)
+        );
+
+        // An explicit format skips detection
+        let mut pipeline = Pipeline::new(Envelope::Auto, Some(FORMATS[1]));
+        assert_eq!(
+            pipeline.ingest_lines(&mut stats, "x.log", &[r#"{"ClientHost":"10.0.0.3"}"#]),
+            (0, 1)
+        );
+    }
+
+    #[test]
+    fn test_odd_first_line_does_not_decide_the_format() {
+        let mut stats = StatsCollector::new();
+        let mut pipeline = Pipeline::new(Envelope::Auto, None);
+
+        // A stdin stream delivering one line per batch: a stray CLF line, a banner,
+        // then JSON
+        let clf = r#"10.0.0.1 - - [15/Jan/2024:10:30:00 +0000] "GET / HTTP/1.1" 200 5 "-" "-""#;
+        assert_eq!(pipeline.ingest_lines(&mut stats, "-", &[clf]), (1, 0));
+        assert_eq!(
+            pipeline.ingest_lines(&mut stats, "-", &["Traefik starting"]),
+            (0, 1)
+        );
+        for i in 0..DETECT_SAMPLE_LINES {
+            let json = format!(r#"{{"ClientHost":"10.0.1.{}"}}"#, i);
+            assert_eq!(pipeline.ingest_lines(&mut stats, "-", &[json]), (1, 0));
+        }
+
+        // JSON won the vote, so CLF lines are now malformed
+        assert_eq!(pipeline.ingest_lines(&mut stats, "-", &[clf]), (0, 1));
+        assert_eq!(stats.total_requests(), 1 + DETECT_SAMPLE_LINES);
+    }
+
+    /// Lines per second of parsing the same generated input into owned
+    /// `TraefikLogEntry`s and into `BorrowedEntry`s, each added to fresh statistics
+    /// Run with `cargo test --release bench_ingest -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_ingest_owned_vs_borrowed() {
+        use crate::log_entry::{BorrowedEntry, TraefikLogEntry};
+        use std::time::Instant;
+
+        let lines: Vec<String> = (0..200_000)
+            .map(|i| {
+                format!(
+                    r#"{{"ClientAddr":"10.0.{}.{}:52604","ClientHost":"10.0.{}.{}","ClientPort":"52604","ClientUsername":"-","DownstreamContentSize":1534,"DownstreamStatus":200,"Duration":2481377,"OriginContentSize":1534,"OriginDuration":2301122,"OriginStatus":200,"Overhead":180255,"RequestAddr":"api.example.com","RequestContentSize":0,"RequestCount":{},"RequestHost":"api.example.com","RequestMethod":"GET","RequestPath":"/api/items/{}?page={}","RequestPort":"-","RequestProtocol":"HTTP/1.1","RequestScheme":"https","RetryAttempts":0,"RouterName":"api@docker","ServiceAddr":"172.18.0.3:80","ServiceName":"api@docker","ServiceURL":"http://172.18.0.3:80","StartLocal":"2024-01-15T10:30:00.123456789Z","StartUTC":"2024-01-15T10:30:00.123456789Z","entryPointName":"websecure","level":"info","msg":"","request_User-Agent":"curl/8.0","time":"2024-01-15T10:30:00Z"}}"#,
+                    i % 7,
+                    i % 251,
+                    i % 7,
+                    i % 251,
+                    i,
+                    i % 50,
+                    i % 10
+                )
+            })
+            .collect();
+        let rate = |started: Instant| lines.len() as f64 / started.elapsed().as_secs_f64();
+
+        let mut owned_stats = StatsCollector::new();
+        let started = Instant::now();
+        for line in &lines {
+            let mut entry = TraefikLogEntry::from_json_line(line).unwrap();
+            entry.source = Some("bench.log".to_string());
+            owned_stats.add_entry(&entry);
+        }
+        let owned = rate(started);
+
+        let mut borrowed_stats = StatsCollector::new();
+        let started = Instant::now();
+        for line in &lines {
+            let entry = BorrowedEntry::from_json_line(line).unwrap();
+            borrowed_stats.add_borrowed(&entry, "bench.log");
+        }
+        let borrowed = rate(started);
+
+        assert_eq!(
+            borrowed_stats.total_requests(),
+            owned_stats.total_requests()
+        );
+        assert_eq!(borrowed_stats.unique_ips(), owned_stats.unique_ips());
+        println!(
+            "owned: {:.0} lines/s, borrowed: {:.0} lines/s ({:.1}x)",
+            owned,
+            borrowed,
+            borrowed / owned
+        );
+    }
+}
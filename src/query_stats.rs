@@ -0,0 +1,515 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasherDefault, DefaultHasher, Hash, Hasher};
+
+use crate::path_normalizer::percent_decode;
+
+/// Default number of parameter names, and of top values per name, tracked across all
+/// requests
+pub const DEFAULT_GLOBAL_LIMIT: usize = 1000;
+
+/// Default number of parameter names, and of top values per name, tracked for one IP
+pub const DEFAULT_IP_LIMIT: usize = 100;
+
+/// Bits of a value's hash that select its sketch register, across all requests:
+/// 1024 one-byte registers, for a standard error of about 3%
+pub const GLOBAL_SKETCH_BITS: u32 = 10;
+
+/// Bits of a value's hash that select its sketch register, for one IP: 256 one-byte
+/// registers, for a standard error of about 6.5%, since there can be many busy IPs
+pub const IP_SKETCH_BITS: u32 = 8;
+
+/// Sketch registers per distinct value counted exactly before switching to the sketch;
+/// the exact set of 8-byte hashes then takes about as much memory as the sketch
+const REGISTERS_PER_EXACT_VALUE: usize = 8;
+
+/// Distinct values counted exactly before switching to a sketch of `1 << bits` registers
+fn exact_distinct(bits: u32) -> usize {
+    (1 << bits) / REGISTERS_PER_EXACT_VALUE
+}
+
+/// How much of the query parameters is kept in memory
+/// Across all requests, at most `global` names are tracked, each with up to `global`
+/// values and a distinct counter of at most 1 KiB. Each IP tracks at most `per_ip`
+/// names, each with up to `per_ip` values and a distinct counter of at most 256 bytes:
+/// with the defaults, 10,000 values and 25 KiB of counters per IP, plus the values'
+/// text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryLimits {
+    /// Parameter names, and top values per name, tracked across all requests
+    pub global: usize,
+    /// Parameter names, and top values per name, tracked for each IP
+    pub per_ip: usize,
+}
+
+impl Default for QueryLimits {
+    fn default() -> Self {
+        QueryLimits {
+            global: DEFAULT_GLOBAL_LIMIT,
+            per_ip: DEFAULT_IP_LIMIT,
+        }
+    }
+}
+
+/// Number of distinct values of a parameter
+/// Counted exactly for the first few values, then estimated with a HyperLogLog sketch
+/// of `1 << bits` registers, so a cache-busting parameter carrying a fresh value on
+/// every request takes a fixed amount of memory. Counters that are merged must use the
+/// same `bits`; merging then gives the same result in any order.
+#[derive(Debug, Clone)]
+pub enum DistinctCounter {
+    /// Hashes of every value seen
+    Exact(HashSet<u64, BuildHasherDefault<PrehashedHasher>>),
+    /// Highest hash rank seen per register
+    Sketch(Box<[u8]>),
+}
+
+impl Default for DistinctCounter {
+    fn default() -> Self {
+        DistinctCounter::Exact(HashSet::default())
+    }
+}
+
+impl DistinctCounter {
+    /// Count one occurrence of a value, with a sketch of `1 << bits` registers once
+    /// there are too many to count exactly
+    pub fn add(&mut self, value: &str, bits: u32) {
+        self.add_hash(hash_value(value), bits);
+    }
+
+    /// Number of distinct values, estimated if `is_estimate`
+    pub fn count(&self) -> usize {
+        let registers = match self {
+            DistinctCounter::Exact(hashes) => return hashes.len(),
+            DistinctCounter::Sketch(registers) => registers,
+        };
+
+        let m = registers.len() as f64;
+        let sum: f64 = registers
+            .iter()
+            .map(|rank| (-f64::from(*rank)).exp2())
+            .sum();
+        let estimate = 0.7213 / (1.0 + 1.079 / m) * m * m / sum;
+
+        // Linear counting is more accurate while many registers are still unused
+        let unused = registers.iter().filter(|rank| **rank == 0).count();
+        if estimate <= 2.5 * m && unused > 0 {
+            (m * (m / unused as f64).ln()).round() as usize
+        } else {
+            estimate.round() as usize
+        }
+    }
+
+    /// Whether too many values were seen to count them exactly
+    pub fn is_estimate(&self) -> bool {
+        matches!(self, DistinctCounter::Sketch(_))
+    }
+
+    /// Count the values of another counter into this one
+    pub fn merge(&mut self, other: DistinctCounter, bits: u32) {
+        match other {
+            DistinctCounter::Exact(hashes) => {
+                for hash in hashes {
+                    self.add_hash(hash, bits);
+                }
+            }
+            DistinctCounter::Sketch(other_registers) => {
+                debug_assert_eq!(other_registers.len(), 1 << bits);
+                self.switch_to_sketch(bits);
+                if let DistinctCounter::Sketch(registers) = self {
+                    for (rank, other_rank) in registers.iter_mut().zip(other_registers.iter()) {
+                        *rank = (*rank).max(*other_rank);
+                    }
+                }
+            }
+        }
+    }
+
+    fn add_hash(&mut self, hash: u64, bits: u32) {
+        match self {
+            DistinctCounter::Exact(hashes) => {
+                hashes.insert(hash);
+                if hashes.len() > exact_distinct(bits) {
+                    self.switch_to_sketch(bits);
+                }
+            }
+            DistinctCounter::Sketch(registers) => sketch_insert(registers, hash),
+        }
+    }
+
+    /// Replace the exact set with a sketch of the same values
+    fn switch_to_sketch(&mut self, bits: u32) {
+        if let DistinctCounter::Exact(hashes) = self {
+            let mut registers = vec![0; 1 << bits].into_boxed_slice();
+            for hash in hashes.iter() {
+                sketch_insert(&mut registers, *hash);
+            }
+            *self = DistinctCounter::Sketch(registers);
+        }
+    }
+}
+
+/// Record a hash in a sketch: its top bits select a register, which keeps the highest
+/// rank (position of the first set bit) of the remaining bits
+fn sketch_insert(registers: &mut [u8], hash: u64) {
+    let bits = registers.len().trailing_zeros();
+    let index = (hash >> (64 - bits)) as usize;
+    let rank = (hash << bits).leading_zeros().min(64 - bits) + 1;
+    registers[index] = registers[index].max(rank as u8);
+}
+
+/// Hash a value the same way in every worker, so their counters can be merged
+fn hash_value(value: &str) -> u64 {
+    // DefaultHasher::new uses fixed keys
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Hasher for sets of value hashes, which are already well mixed and need no rehashing
+#[derive(Default)]
+pub struct PrehashedHasher(u64);
+
+impl Hasher for PrehashedHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = self.0.rotate_left(8) ^ u64::from(*byte);
+        }
+    }
+
+    fn write_u64(&mut self, hash: u64) {
+        self.0 = hash;
+    }
+}
+
+/// An item of a bounded summary, ranked by its count
+trait Counted {
+    fn count_mut(&mut self) -> &mut usize;
+}
+
+impl Counted for usize {
+    fn count_mut(&mut self) -> &mut usize {
+        self
+    }
+}
+
+/// Make room in a full summary for a key it does not track (the Misra-Gries step):
+/// every count drops by one and keys left at zero are dropped. A tracked count then
+/// falls short by at most total / (limit + 1), so every key sent more often than that
+/// stays tracked, whether it was seen first or last.
+fn decrement_all<T: Counted>(summary: &mut HashMap<String, T>) {
+    summary.retain(|_, item| {
+        let count = item.count_mut();
+        *count -= 1;
+        *count > 0
+    });
+}
+
+/// Cut a merged summary back to `limit` keys by lowering every count by the count of
+/// the largest key that does not fit, which keeps the error bound of `decrement_all`
+/// Returns whether any key was dropped
+fn truncate<T: Counted>(summary: &mut HashMap<String, T>, limit: usize) -> bool {
+    if summary.len() <= limit {
+        return false;
+    }
+    let mut counts: Vec<usize> = summary.values_mut().map(|item| *item.count_mut()).collect();
+    counts.sort_unstable_by(|a, b| b.cmp(a));
+    let cut = counts[limit];
+    summary.retain(|_, item| {
+        let count = item.count_mut();
+        *count -= cut.min(*count);
+        *count > 0
+    });
+    true
+}
+
+/// Statistics of one query parameter
+#[derive(Debug, Clone, Default)]
+pub struct ParamStats {
+    /// Number of times the parameter was sent (a lower bound if its `QueryStats` is
+    /// saturated)
+    pub count: usize,
+    /// Most common values with their counts, up to the tracking limit
+    pub values: HashMap<String, usize>,
+    /// Number of distinct values
+    pub distinct: DistinctCounter,
+    /// Whether more distinct values were seen than are tracked, so value counts are
+    /// lower bounds
+    pub saturated: bool,
+}
+
+impl Counted for ParamStats {
+    fn count_mut(&mut self) -> &mut usize {
+        &mut self.count
+    }
+}
+
+impl ParamStats {
+    /// Number of distinct values sent, estimated if `distinct.is_estimate()`
+    pub fn distinct_values(&self) -> usize {
+        self.distinct.count()
+    }
+
+    /// Get the top N values by count
+    /// Returns vector of (value, count) tuples sorted by count descending, then by value
+    pub fn top_values(&self, n: usize) -> Vec<(String, usize)> {
+        let mut values: Vec<(String, usize)> = self
+            .values
+            .iter()
+            .map(|(value, count)| (value.clone(), *count))
+            .collect();
+        values.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        values.into_iter().take(n).collect()
+    }
+
+    /// Count one occurrence of a value
+    fn add_value(&mut self, value: &str, limit: usize, sketch_bits: u32) {
+        self.count += 1;
+        self.distinct.add(value, sketch_bits);
+        if let Some(count) = self.values.get_mut(value) {
+            *count += 1;
+        } else if self.values.len() < limit {
+            self.values.insert(value.to_string(), 1);
+        } else {
+            decrement_all(&mut self.values);
+            self.saturated = true;
+        }
+    }
+
+    /// Merge the statistics of the same parameter collected separately
+    fn merge(&mut self, other: ParamStats, limit: usize, sketch_bits: u32) {
+        self.count += other.count;
+        self.distinct.merge(other.distinct, sketch_bits);
+        for (value, count) in other.values {
+            *self.values.entry(value).or_insert(0) += count;
+        }
+        self.saturated |= other.saturated;
+        self.saturated |= truncate(&mut self.values, limit);
+    }
+}
+
+/// Query-string parameter statistics of a set of requests
+/// Memory is bounded: at most `limit` names, and `limit` values per name, are tracked
+/// as a Misra-Gries summary, which keeps the most common ones however late they first
+/// appear. Distinct values are counted separately, estimated once there are many, since
+/// cache-busting parameters can carry a fresh value on every request.
+#[derive(Debug, Clone)]
+pub struct QueryStats {
+    params: HashMap<String, ParamStats>,
+    limit: usize,
+    /// Size of the distinct value sketches, as bits selecting a register
+    sketch_bits: u32,
+    /// Whether more distinct parameter names were seen than are tracked
+    pub saturated: bool,
+}
+
+impl QueryStats {
+    /// Create empty statistics tracking up to `limit` names and values per name, with
+    /// distinct value sketches of `1 << sketch_bits` registers
+    pub fn new(limit: usize, sketch_bits: u32) -> Self {
+        QueryStats {
+            params: HashMap::new(),
+            limit,
+            sketch_bits,
+            saturated: false,
+        }
+    }
+
+    /// Count the parameters of a query string (the part of a path after `?`)
+    pub fn add_query(&mut self, query: &str) {
+        for (name, value) in parse_query(query) {
+            if let Some(param) = self.params.get_mut(name.as_ref()) {
+                param.add_value(&value, self.limit, self.sketch_bits);
+            } else if self.params.len() < self.limit {
+                let mut param = ParamStats::default();
+                param.add_value(&value, self.limit, self.sketch_bits);
+                self.params.insert(name.into_owned(), param);
+            } else {
+                decrement_all(&mut self.params);
+                self.saturated = true;
+            }
+        }
+    }
+
+    /// Get the top N parameters by count
+    /// Returns vector of (name, stats) tuples sorted by count descending, then by name
+    pub fn top_params(&self, n: usize) -> Vec<(&str, &ParamStats)> {
+        let mut params: Vec<(&str, &ParamStats)> = self
+            .params
+            .iter()
+            .map(|(name, param)| (name.as_str(), param))
+            .collect();
+        params.sort_by(|a, b| b.1.count.cmp(&a.1.count).then_with(|| a.0.cmp(b.0)));
+        params.into_iter().take(n).collect()
+    }
+
+    /// Whether no parameters were seen
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+
+    /// Merge statistics collected separately into these, within this tracking limit
+    /// The result does not depend on the order statistics are merged in
+    pub fn merge(&mut self, other: QueryStats) {
+        self.saturated |= other.saturated;
+        for (name, other_param) in other.params {
+            self.params
+                .entry(name)
+                .or_default()
+                .merge(other_param, self.limit, self.sketch_bits);
+        }
+        self.saturated |= truncate(&mut self.params, self.limit);
+    }
+}
+
+/// Split a query string into decoded (name, value) pairs
+/// `+` stands for a space; parameters without `=` have an empty value
+pub fn parse_query(query: &str) -> impl Iterator<Item = (Cow<'_, str>, Cow<'_, str>)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode_component(name), decode_component(value))
+        })
+}
+
+/// Decode a form-encoded query component
+fn decode_component(component: &str) -> Cow<'_, str> {
+    if component.contains('+') {
+        Cow::Owned(percent_decode(&component.replace('+', " ")).into_owned())
+    } else {
+        percent_decode(component)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_query() {
+        let pairs: Vec<(String, String)> = parse_query("id=7&q=hello+world%21&&debug&e=a%3Db")
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ("id".to_string(), "7".to_string()),
+                ("q".to_string(), "hello world!".to_string()),
+                ("debug".to_string(), String::new()),
+                ("e".to_string(), "a=b".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_query_stats_counts() {
+        let mut stats = QueryStats::new(10, GLOBAL_SKETCH_BITS);
+        for query in ["id=1", "id=1&_=a", "id=2&_=b"] {
+            stats.add_query(query);
+        }
+
+        let top = stats.top_params(10);
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].0, "id");
+        assert_eq!(top[0].1.count, 3);
+        assert_eq!(top[0].1.distinct_values(), 2);
+        assert_eq!(
+            top[0].1.top_values(2),
+            vec![("1".to_string(), 2), ("2".to_string(), 1)]
+        );
+        assert_eq!(top[1].0, "_");
+        assert!(!top[0].1.saturated && !stats.saturated);
+    }
+
+    #[test]
+    fn test_late_common_values_and_names_are_tracked() {
+        let mut stats = QueryStats::new(3, GLOBAL_SKETCH_BITS);
+        // Many one-off values and names fill the summary before the common ones appear
+        for i in 0..50 {
+            stats.add_query(&format!("id={}&p{}=1", i, i));
+        }
+        for _ in 0..30 {
+            stats.add_query("id=hot&q=shoes");
+        }
+
+        let top = stats.top_params(2);
+        assert_eq!(top[0].0, "id");
+        assert_eq!(top[1].0, "q");
+        assert_eq!(top[0].1.top_values(1)[0].0, "hot");
+        // Every value is still counted as distinct
+        assert_eq!(top[0].1.distinct_values(), 51);
+        assert!(top[0].1.saturated && stats.saturated);
+    }
+
+    #[test]
+    fn test_distinct_values_are_estimated_in_bounded_memory() {
+        let mut stats = QueryStats::new(10, GLOBAL_SKETCH_BITS);
+        for i in 0..128 {
+            stats.add_query(&format!("_={}", i));
+        }
+        let param = stats.top_params(1)[0].1;
+        assert!(!param.distinct.is_estimate());
+        assert_eq!(param.distinct_values(), 128);
+
+        for i in 128..20_000 {
+            stats.add_query(&format!("_={}", i));
+        }
+        let param = stats.top_params(1)[0].1;
+        assert!(param.distinct.is_estimate());
+        assert!((18_000..=22_000).contains(&param.distinct_values()));
+        assert!(param.values.len() <= 10);
+    }
+
+    #[test]
+    fn test_per_ip_sketches_are_smaller() {
+        let mut stats = QueryStats::new(10, IP_SKETCH_BITS);
+        for i in 0..32 {
+            stats.add_query(&format!("_={}", i));
+        }
+        assert!(!stats.top_params(1)[0].1.distinct.is_estimate());
+
+        for i in 32..20_000 {
+            stats.add_query(&format!("_={}", i));
+        }
+        let param = stats.top_params(1)[0].1;
+        match &param.distinct {
+            DistinctCounter::Sketch(registers) => assert_eq!(registers.len(), 256),
+            other => panic!("expected a sketch: {:?}", other),
+        }
+        assert!((16_000..=24_000).contains(&param.distinct_values()));
+    }
+
+    #[test]
+    fn test_merge_does_not_depend_on_order() {
+        let build = |queries: &[String]| {
+            let mut stats = QueryStats::new(3, GLOBAL_SKETCH_BITS);
+            for query in queries {
+                stats.add_query(query);
+            }
+            stats
+        };
+        let one_off: Vec<String> = (0..500).map(|i| format!("id={}", i)).collect();
+        let common: Vec<String> = (0..300).map(|i| format!("id=hot&n={}", i % 2)).collect();
+
+        let mut forward = build(&one_off);
+        forward.merge(build(&common));
+        let mut backward = build(&common);
+        backward.merge(build(&one_off));
+
+        for merged in [&forward, &backward] {
+            let id = merged.top_params(1)[0].1;
+            assert_eq!(id.count, 800);
+            assert_eq!(id.top_values(1), vec![("hot".to_string(), 300)]);
+            assert!(id.saturated);
+        }
+        let (forward, backward) = (forward.top_params(1)[0].1, backward.top_params(1)[0].1);
+        assert_eq!(forward.distinct_values(), backward.distinct_values());
+        assert!((450..=550).contains(&forward.distinct_values()));
+    }
+}
@@ -0,0 +1,186 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::path::Path;
+use std::sync::mpsc::SyncSender;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::archive_reader::ArchiveReader;
+use crate::envelope::{Envelope, EnvelopeDecoder};
+use crate::event::MonitorEvent;
+use crate::format::parse_line;
+
+/// Lines read from the log at a time
+const READ_BATCH_LINES: usize = 1000;
+
+/// Maps log timestamps to wall-clock instants for a replay
+/// The first timestamp seen is replayed immediately; later ones are scheduled at their
+/// distance from it, divided by the speed multiplier
+pub struct ReplayClock {
+    speed: f64,
+    origin: Option<(DateTime<Utc>, Instant)>,
+}
+
+impl ReplayClock {
+    /// Create a clock replaying `speed` times faster than real time
+    pub fn new(speed: f64) -> Self {
+        ReplayClock {
+            speed,
+            origin: None,
+        }
+    }
+
+    /// Instant at which an entry logged at `time` is due
+    /// Entries older than the first one (out-of-order writes) are due immediately
+    pub fn due(&mut self, time: DateTime<Utc>, now: Instant) -> Instant {
+        let (origin_time, origin_instant) = *self.origin.get_or_insert((time, now));
+        let offset = (time - origin_time).to_std().unwrap_or(Duration::ZERO);
+        origin_instant + offset.div_f64(self.speed)
+    }
+}
+
+/// Spawn a thread that replays an existing log, paced by each entry's `StartUTC`
+/// Lines are sent to the monitoring loop as if they were being written live, `speed`
+/// times faster than they originally were. Lines without a timestamp are sent along
+/// with the entry before them. The thread ends at the end of the log or when the
+/// receiving side is dropped.
+pub fn spawn_replay(
+    path: &Path,
+    speed: f64,
+    envelope: Envelope,
+    tx: SyncSender<MonitorEvent>,
+) -> Result<JoinHandle<()>> {
+    let mut reader = ArchiveReader::open(path)?;
+    let source = path.display().to_string();
+
+    Ok(thread::spawn(move || {
+        // Envelopes are unwrapped here to read the timestamps; unwrapped lines pass
+        // through the pipeline's decoder unchanged
+        let mut decoder = EnvelopeDecoder::new(envelope);
+        let mut clock = ReplayClock::new(speed);
+        let mut pending = Vec::new();
+
+        loop {
+            let lines = match reader.read_lines(READ_BATCH_LINES) {
+                Ok(lines) if !lines.is_empty() => lines,
+                Ok(_) => break, // End of log
+                Err(e) => {
+                    eprintln!("Error replaying {}: {:#}", source, e);
+                    break;
+                }
+            };
+            let mut invalid_utf8 = reader.take_invalid_utf8();
+
+            for line in lines {
+                let Some(line) = decoder.decode(&line) else {
+                    continue;
+                };
+
+                let time = parse_line(&line).ok().and_then(|entry| entry.start_time());
+                if let Some(time) = time {
+                    let due = clock.due(time, Instant::now());
+                    let wait = due.saturating_duration_since(Instant::now());
+                    if !wait.is_zero() {
+                        // Everything due so far goes out before waiting for this entry
+                        if !send(&tx, &source, &mut pending, &mut invalid_utf8) {
+                            return;
+                        }
+                        thread::sleep(wait);
+                    }
+                }
+                pending.push(line.into_owned());
+            }
+
+            if !send(&tx, &source, &mut pending, &mut invalid_utf8) {
+                return;
+            }
+        }
+    }))
+}
+
+/// Send pending lines to the monitoring loop
+/// Returns false once the receiving side is gone
+fn send(
+    tx: &SyncSender<MonitorEvent>,
+    source: &str,
+    pending: &mut Vec<String>,
+    invalid_utf8: &mut usize,
+) -> bool {
+    if pending.is_empty() && *invalid_utf8 == 0 {
+        return true;
+    }
+
+    let event = MonitorEvent::Lines {
+        source: source.to_string(),
+        lines: std::mem::take(pending),
+        invalid_utf8: std::mem::take(invalid_utf8),
+    };
+    tx.send(event).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use std::sync::mpsc;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().to_utc()
+    }
+
+    #[test]
+    fn test_replay_clock_applies_speed() {
+        let now = Instant::now();
+        let mut clock = ReplayClock::new(10.0);
+
+        assert_eq!(clock.due(at("2024-01-15T10:00:00Z"), now), now);
+        assert_eq!(
+            clock.due(at("2024-01-15T10:00:30Z"), now + Duration::from_secs(1)),
+            now + Duration::from_secs(3)
+        );
+        // Out-of-order entries are due right away
+        assert_eq!(clock.due(at("2024-01-15T09:59:00Z"), now), now);
+    }
+
+    #[test]
+    fn test_replay_paces_entries() {
+        let test_file = "test_replay_paces_entries.log";
+        let mut file = fs::File::create(test_file).unwrap();
+        for (ip, time) in [
+            ("10.0.0.1", "2024-01-15T10:00:00Z"),
+            ("10.0.0.2", "2024-01-15T10:00:00.1Z"),
+            ("10.0.0.3", "2024-01-15T10:00:10Z"),
+        ] {
+            writeln!(file, r#"{{"ClientHost":"{}","StartUTC":"{}"}}"#, ip, time).unwrap();
+        }
+        writeln!(file, "not json").unwrap();
+        drop(file);
+
+        // 100x speed: the last entry arrives about 100 ms after the first
+        let (tx, rx) = mpsc::sync_channel(16);
+        let started = Instant::now();
+        spawn_replay(Path::new(test_file), 100.0, Envelope::Auto, tx)
+            .unwrap()
+            .join()
+            .unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(100));
+
+        let lines: Vec<String> = rx
+            .iter()
+            .flat_map(|event| match event {
+                MonitorEvent::Lines { source, lines, .. } => {
+                    assert_eq!(source, test_file);
+                    lines
+                }
+                other => panic!("unexpected event: {:?}", other),
+            })
+            .collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[2].contains("10.0.0.3"));
+        assert_eq!(lines[3], "not json");
+
+        // Clean up
+        fs::remove_file(test_file).ok();
+    }
+}
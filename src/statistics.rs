@@ -1,5 +1,36 @@
-use crate::log_entry::TraefikLogEntry;
-use std::collections::HashMap;
+use crate::client_ip::{IpResolver, IpSource, ResolvedIp};
+use crate::log_entry::{BorrowedEntry, TraefikLogEntry};
+use crate::path_normalizer::PathNormalizer;
+use crate::query_stats::{GLOBAL_SKETCH_BITS, IP_SKETCH_BITS, QueryLimits, QueryStats};
+use chrono::{DateTime, Utc};
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// Shared copies of request paths, so a path requested by many IPs is stored once
+#[derive(Debug, Default)]
+pub struct PathInterner {
+    paths: HashSet<Arc<str>>,
+}
+
+impl PathInterner {
+    /// Get the shared copy of a path, allocating it on first use
+    pub fn intern(&mut self, path: &str) -> Arc<str> {
+        if let Some(shared) = self.paths.get(path) {
+            return shared.clone();
+        }
+        let shared: Arc<str> = Arc::from(path);
+        self.paths.insert(shared.clone());
+        shared
+    }
+
+    /// Take over the paths of another interner, keeping the copy already known here
+    pub fn absorb(&mut self, other: PathInterner) {
+        self.paths.extend(other.paths);
+    }
+}
 
 /// Statistics for a single IP address
 #[derive(Debug, Clone)]
@@ -7,22 +38,71 @@ pub struct IpStats {
     /// Total number of requests from this IP
     pub request_count: usize,
     /// Map of request paths to their access counts
-    pub paths: HashMap<String, usize>,
+    pub paths: HashMap<Arc<str>, usize>,
+    /// Earliest request timestamp seen from this IP
+    pub first_seen: Option<DateTime<Utc>>,
+    /// Latest request timestamp seen from this IP
+    pub last_seen: Option<DateTime<Utc>>,
+    /// Query-string parameters sent by this IP
+    pub query: QueryStats,
 }
 
 impl IpStats {
-    /// Create new empty statistics for an IP
-    pub fn new() -> Self {
+    /// Create new empty statistics for an IP, tracking up to `query_limit` query
+    /// parameters and values per parameter
+    pub fn new(query_limit: usize) -> Self {
         IpStats {
             request_count: 0,
             paths: HashMap::new(),
+            first_seen: None,
+            last_seen: None,
+            query: QueryStats::new(query_limit, IP_SKETCH_BITS),
         }
     }
 
-    /// Add a request to this IP's statistics
-    pub fn add_request(&mut self, path: &str) {
+    /// Add a request made at `time` to this IP's statistics
+    /// Paths new to this IP are stored as their shared copy from `interner`
+    pub fn add_request(&mut self, path: &str, time: DateTime<Utc>, interner: &mut PathInterner) {
         self.request_count += 1;
-        *self.paths.entry(path.to_string()).or_insert(0) += 1;
+        self.count_path(path, 1, interner);
+        self.record_seen(Some(time), Some(time));
+    }
+
+    /// Add to a path's count without copying the path if it is already known
+    fn count_path(&mut self, path: &str, count: usize, interner: &mut PathInterner) {
+        match self.paths.get_mut(path) {
+            Some(existing) => *existing += count,
+            None => {
+                self.paths.insert(interner.intern(path), count);
+            }
+        }
+    }
+
+    /// Swap the path keys for the shared copies in `interner`
+    /// Used when statistics move to another collector, whose interner may already hold
+    /// the same paths; only the keys are replaced, the strings are not copied
+    fn reintern(&mut self, interner: &mut PathInterner) {
+        self.paths = std::mem::take(&mut self.paths)
+            .into_iter()
+            .map(|(path, count)| (interner.intern(&path), count))
+            .collect();
+    }
+
+    /// Widen the first/last seen range to include the given times
+    fn record_seen(&mut self, first: Option<DateTime<Utc>>, last: Option<DateTime<Utc>>) {
+        // Entries can arrive out of order, e.g. from several files
+        self.first_seen = self.first_seen.into_iter().chain(first).min();
+        self.last_seen = self.last_seen.into_iter().chain(last).max();
+    }
+
+    /// Merge another IP's statistics into this one
+    pub fn merge(&mut self, other: IpStats, interner: &mut PathInterner) {
+        self.request_count += other.request_count;
+        for (path, count) in other.paths {
+            self.count_path(&path, count, interner);
+        }
+        self.query.merge(other.query);
+        self.record_seen(other.first_seen, other.last_seen);
     }
 
     /// Get the top N most accessed paths by this IP
@@ -31,47 +111,158 @@ impl IpStats {
         let mut paths: Vec<(String, usize)> = self
             .paths
             .iter()
-            .map(|(path, count)| (path.clone(), *count))
+            .map(|(path, count)| (path.to_string(), *count))
             .collect();
 
         // Sort by count descending
-        paths.sort_by(|a, b| b.1.cmp(&a.1));
+        paths.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
 
         // Take top N
         paths.into_iter().take(n).collect()
     }
 }
 
+/// Counts of input lines that could not be used as-is, by error class
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ErrorCounts {
+    /// Lines that could not be parsed as a log entry
+    pub malformed: usize,
+    /// Lines containing invalid UTF-8, decoded lossily before parsing
+    pub invalid_utf8: usize,
+    /// Entries whose ClientHost and ClientAddr are both missing or not an IP address
+    pub no_client_ip: usize,
+}
+
+impl ErrorCounts {
+    /// Total number of problem lines across all classes
+    pub fn total(&self) -> usize {
+        self.malformed + self.invalid_utf8 + self.no_client_ip
+    }
+}
+
 /// Collector for all IP statistics
 pub struct StatsCollector {
     /// Map of IP addresses to their statistics
-    stats: HashMap<String, IpStats>,
+    stats: HashMap<IpAddr, IpStats>,
     /// Total number of requests tracked
     total_requests: usize,
+    /// Map of entry sources (e.g. log file paths) to their request counts
+    sources: HashMap<String, usize>,
+    /// Input lines that could not be used as-is
+    errors: ErrorCounts,
+    /// Determines the client IP each entry is counted under
+    resolver: IpResolver,
+    /// Map of IP resolution methods to the number of requests they resolved
+    ip_sources: HashMap<IpSource, usize>,
+    /// Request paths shared by all IPs' statistics
+    interner: PathInterner,
+    /// Turns paths into route templates (None = count paths as logged)
+    normalizer: Option<PathNormalizer>,
+    /// Query-string parameters across all requests
+    query: QueryStats,
+    /// Query parameters and values per parameter tracked for each IP
+    ip_query_limit: usize,
 }
 
 impl StatsCollector {
     /// Create a new empty statistics collector
+    #[cfg(test)]
     pub fn new() -> Self {
+        Self::with_resolver(IpResolver::default())
+    }
+
+    /// Create a new empty statistics collector that counts requests under the client IP
+    /// determined by `resolver`
+    pub fn with_resolver(resolver: IpResolver) -> Self {
         StatsCollector {
             stats: HashMap::new(),
             total_requests: 0,
+            sources: HashMap::new(),
+            errors: ErrorCounts::default(),
+            resolver,
+            ip_sources: HashMap::new(),
+            interner: PathInterner::default(),
+            normalizer: None,
+            query: QueryStats::new(QueryLimits::default().global, GLOBAL_SKETCH_BITS),
+            ip_query_limit: QueryLimits::default().per_ip,
         }
     }
 
+    /// Count paths by their route template instead of as logged
+    pub fn with_normalizer(mut self, normalizer: PathNormalizer) -> Self {
+        self.normalizer = Some(normalizer);
+        self
+    }
+
+    /// Track as many query parameters and values as `limits` allow
+    pub fn with_query_limits(mut self, limits: QueryLimits) -> Self {
+        self.query = QueryStats::new(limits.global, GLOBAL_SKETCH_BITS);
+        self.ip_query_limit = limits.per_ip;
+        self
+    }
+
     /// Add a log entry to the statistics
     pub fn add_entry(&mut self, entry: &TraefikLogEntry) {
-        // Extract IP and path
-        let ip = match entry.get_ip() {
-            Some(ip) => ip,
-            None => return, // Skip entries without IP
+        // Resolve the client IP; entries without one are only counted as problems
+        let Some(resolved) = self.resolver.resolve(entry) else {
+            self.errors.no_client_ip += 1;
+            return;
+        };
+        self.count_request(
+            resolved,
+            entry.get_path(),
+            entry.timestamp,
+            entry.source.as_deref(),
+        );
+    }
+
+    /// Add a borrowed log entry read from `source` to the statistics
+    pub fn add_borrowed(&mut self, entry: &BorrowedEntry, source: &str) {
+        let Some(resolved) = self.resolver.resolve_borrowed(entry) else {
+            self.errors.no_client_ip += 1;
+            return;
         };
+        let time = entry.start_time();
+        self.count_request(resolved, entry.get_path(), time, Some(source));
+    }
 
-        let path = entry.get_path();
+    /// Count one request from a resolved client, made at `time` or else just now
+    /// Keys are only copied the first time they are seen
+    fn count_request(
+        &mut self,
+        resolved: ResolvedIp,
+        path: &str,
+        time: Option<DateTime<Utc>>,
+        source: Option<&str>,
+    ) {
+        let time = time.unwrap_or_else(Utc::now);
+        *self.ip_sources.entry(resolved.source).or_insert(0) += 1;
+        let query = path.split_once('?').map(|(_, query)| query);
+        let path = match self.normalizer {
+            Some(ref normalizer) => normalizer.normalize(path),
+            None => Cow::Borrowed(path),
+        };
 
         // Update or create IP stats
-        let ip_stats = self.stats.entry(ip).or_insert_with(IpStats::new);
-        ip_stats.add_request(&path);
+        let ip_stats = self
+            .stats
+            .entry(resolved.ip)
+            .or_insert_with(|| IpStats::new(self.ip_query_limit));
+        ip_stats.add_request(&path, time, &mut self.interner);
+        if let Some(query) = query {
+            ip_stats.query.add_query(query);
+            self.query.add_query(query);
+        }
+
+        // Count the request against its source, if tagged
+        if let Some(source) = source {
+            match self.sources.get_mut(source) {
+                Some(count) => *count += 1,
+                None => {
+                    self.sources.insert(source.to_string(), 1);
+                }
+            }
+        }
 
         // Increment total requests
         self.total_requests += 1;
@@ -79,8 +270,8 @@ impl StatsCollector {
 
     /// Get the top N IPs by request count
     /// Returns vector of (ip, stats, percentage) tuples sorted by request count descending
-    pub fn get_top_ips(&self, n: usize) -> Vec<(String, &IpStats, f64)> {
-        let mut ips: Vec<(String, &IpStats, f64)> = self
+    pub fn get_top_ips(&self, n: usize) -> Vec<(IpAddr, &IpStats, f64)> {
+        let mut ips: Vec<(IpAddr, &IpStats, f64)> = self
             .stats
             .iter()
             .map(|(ip, stats)| {
@@ -89,17 +280,98 @@ impl StatsCollector {
                 } else {
                     0.0
                 };
-                (ip.clone(), stats, percentage)
+                (*ip, stats, percentage)
             })
             .collect();
 
         // Sort by request count descending
-        ips.sort_by(|a, b| b.1.request_count.cmp(&a.1.request_count));
+        ips.sort_by_key(|(_, stats, _)| std::cmp::Reverse(stats.request_count));
 
         // Take top N
         ips.into_iter().take(n).collect()
     }
 
+    /// Get the request count of every source
+    /// Returns vector of (source, count, percentage) tuples sorted by count descending
+    pub fn get_sources(&self) -> Vec<(String, usize, f64)> {
+        let mut sources: Vec<(String, usize, f64)> = self
+            .sources
+            .iter()
+            .map(|(source, count)| {
+                let percentage = if self.total_requests > 0 {
+                    (*count as f64 / self.total_requests as f64) * 100.0
+                } else {
+                    0.0
+                };
+                (source.clone(), *count, percentage)
+            })
+            .collect();
+
+        // Sort by count descending, then by name for a stable display
+        sources.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        sources
+    }
+
+    /// Record a line that could not be parsed as a log entry
+    pub fn record_malformed(&mut self) {
+        self.errors.malformed += 1;
+    }
+
+    /// Record lines that contained invalid UTF-8
+    pub fn record_invalid_utf8(&mut self, count: usize) {
+        self.errors.invalid_utf8 += count;
+    }
+
+    /// Merge statistics collected separately (e.g. by another worker thread) into this one
+    pub fn merge(&mut self, other: StatsCollector) {
+        // An IP new to this collector is moved over, its path keys swapped for the
+        // copies this collector already shares, so every path is still stored once
+        self.interner.absorb(other.interner);
+        for (ip, mut ip_stats) in other.stats {
+            match self.stats.entry(ip) {
+                Entry::Vacant(entry) => {
+                    ip_stats.reintern(&mut self.interner);
+                    entry.insert(ip_stats);
+                }
+                Entry::Occupied(mut entry) => entry.get_mut().merge(ip_stats, &mut self.interner),
+            }
+        }
+        for (source, count) in other.sources {
+            *self.sources.entry(source).or_insert(0) += count;
+        }
+        for (source, count) in other.ip_sources {
+            *self.ip_sources.entry(source).or_insert(0) += count;
+        }
+        self.query.merge(other.query);
+        self.total_requests += other.total_requests;
+        self.errors.malformed += other.errors.malformed;
+        self.errors.invalid_utf8 += other.errors.invalid_utf8;
+        self.errors.no_client_ip += other.errors.no_client_ip;
+    }
+
+    /// Get the number of requests resolved by each IP resolution method
+    /// Returns (method, count) pairs in method order
+    pub fn get_ip_sources(&self) -> Vec<(IpSource, usize)> {
+        let mut sources: Vec<_> = self
+            .ip_sources
+            .iter()
+            .map(|(source, count)| (*source, *count))
+            .collect();
+        sources.sort();
+        sources
+    }
+
+    /// Get the query-string parameter statistics across all requests
+    pub fn query(&self) -> &QueryStats {
+        &self.query
+    }
+
+    /// Get the counts of problem lines by error class
+    pub fn errors(&self) -> ErrorCounts {
+        self.errors
+    }
+
     /// Get the total number of requests tracked
     pub fn total_requests(&self) -> usize {
         self.total_requests
@@ -117,25 +389,30 @@ mod tests {
 
     #[test]
     fn test_ip_stats_add_request() {
-        let mut stats = IpStats::new();
-        stats.add_request("/api/users");
-        stats.add_request("/api/users");
-        stats.add_request("/api/products");
+        let at = |time: &str| DateTime::parse_from_rfc3339(time).unwrap().to_utc();
+        let mut stats = IpStats::new(QueryLimits::default().per_ip);
+        let mut interner = PathInterner::default();
+        stats.add_request("/api/users", at("2024-01-15T10:00:05Z"), &mut interner);
+        stats.add_request("/api/users", at("2024-01-15T10:00:01Z"), &mut interner);
+        stats.add_request("/api/products", at("2024-01-15T10:00:09Z"), &mut interner);
 
         assert_eq!(stats.request_count, 3);
+        assert_eq!(stats.first_seen, Some(at("2024-01-15T10:00:01Z")));
+        assert_eq!(stats.last_seen, Some(at("2024-01-15T10:00:09Z")));
         assert_eq!(stats.paths.get("/api/users"), Some(&2));
         assert_eq!(stats.paths.get("/api/products"), Some(&1));
     }
 
     #[test]
     fn test_ip_stats_top_paths() {
-        let mut stats = IpStats::new();
-        stats.add_request("/path1");
-        stats.add_request("/path2");
-        stats.add_request("/path2");
-        stats.add_request("/path3");
-        stats.add_request("/path3");
-        stats.add_request("/path3");
+        let mut stats = IpStats::new(QueryLimits::default().per_ip);
+        let mut interner = PathInterner::default();
+        stats.add_request("/path1", Utc::now(), &mut interner);
+        stats.add_request("/path2", Utc::now(), &mut interner);
+        stats.add_request("/path2", Utc::now(), &mut interner);
+        stats.add_request("/path3", Utc::now(), &mut interner);
+        stats.add_request("/path3", Utc::now(), &mut interner);
+        stats.add_request("/path3", Utc::now(), &mut interner);
 
         let top = stats.top_paths(2);
         assert_eq!(top.len(), 2);
@@ -150,22 +427,14 @@ mod tests {
         // Create mock entries
         let entry1 = TraefikLogEntry {
             client_host: Some("192.168.1.1".to_string()),
-            client_addr: None,
             request_path: Some("/api/test".to_string()),
-            request_method: None,
-            request_protocol: None,
-            origin_status: None,
-            downstream_status: None,
+            ..Default::default()
         };
 
         let entry2 = TraefikLogEntry {
             client_host: Some("192.168.1.2".to_string()),
-            client_addr: None,
             request_path: Some("/api/test".to_string()),
-            request_method: None,
-            request_protocol: None,
-            origin_status: None,
-            downstream_status: None,
+            ..Default::default()
         };
 
         // Add entries: 3 from IP1, 1 from IP2
@@ -179,8 +448,278 @@ mod tests {
 
         let top_ips = collector.get_top_ips(10);
         assert_eq!(top_ips.len(), 2);
-        assert_eq!(top_ips[0].0, "192.168.1.1");
+        assert_eq!(top_ips[0].0.to_string(), "192.168.1.1");
         assert_eq!(top_ips[0].1.request_count, 3);
         assert!((top_ips[0].2 - 75.0).abs() < 0.01); // 75%
     }
+
+    #[test]
+    fn test_entries_without_client_ip_are_counted() {
+        let mut collector = StatsCollector::new();
+        for host in [None, Some("not-an-ip"), Some("10.0.0.1")] {
+            collector.add_entry(&TraefikLogEntry {
+                client_host: host.map(str::to_string),
+                ..Default::default()
+            });
+        }
+        let borrowed = BorrowedEntry::from_json_line(r#"{"ClientAddr":"unix:@"}"#).unwrap();
+        collector.add_borrowed(&borrowed, "stdin");
+
+        assert_eq!(collector.total_requests(), 1);
+        assert_eq!(collector.errors().no_client_ip, 3);
+
+        let mut merged = StatsCollector::new();
+        merged.merge(collector);
+        assert_eq!(merged.errors().no_client_ip, 3);
+        assert_eq!(merged.errors().total(), 3);
+    }
+
+    #[test]
+    fn test_entry_without_start_time_is_seen_now() {
+        let mut collector = StatsCollector::new();
+        let before = Utc::now();
+        collector.add_entry(&TraefikLogEntry {
+            client_host: Some("192.168.1.1".to_string()),
+            ..Default::default()
+        });
+
+        let top_ips = collector.get_top_ips(1);
+        let first_seen = top_ips[0].1.first_seen.unwrap();
+        assert!(first_seen >= before && first_seen <= Utc::now());
+    }
+
+    #[test]
+    fn test_stats_collector_per_source_counts() {
+        let mut collector = StatsCollector::new();
+
+        let entry_a = TraefikLogEntry {
+            client_host: Some("192.168.1.1".to_string()),
+            source: Some("a/access.log".to_string()),
+            ..Default::default()
+        };
+        let entry_b = TraefikLogEntry {
+            client_host: Some("192.168.1.1".to_string()),
+            source: Some("b/access.log".to_string()),
+            ..Default::default()
+        };
+
+        collector.add_entry(&entry_a);
+        collector.add_entry(&entry_b);
+        collector.add_entry(&entry_b);
+
+        // IPs are combined across sources
+        assert_eq!(collector.unique_ips(), 1);
+        assert_eq!(collector.get_top_ips(1)[0].1.request_count, 3);
+
+        let sources = collector.get_sources();
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[0].0, "b/access.log");
+        assert_eq!(sources[0].1, 2);
+        assert_eq!(sources[1].0, "a/access.log");
+        assert_eq!(sources[1].1, 1);
+    }
+
+    #[test]
+    fn test_stats_collector_merge() {
+        let entry = |ip: &str, path: &str| TraefikLogEntry {
+            client_host: Some(ip.to_string()),
+            request_path: Some(path.to_string()),
+            ..Default::default()
+        };
+
+        let mut first = StatsCollector::new();
+        first.add_entry(&entry("10.0.0.1", "/a"));
+        first.add_entry(&entry("10.0.0.2", "/a"));
+        first.record_malformed();
+
+        let mut second = StatsCollector::new();
+        second.add_entry(&entry("10.0.0.1", "/a"));
+        second.add_entry(&entry("10.0.0.1", "/b"));
+        second.add_entry(&entry("10.0.0.3", "/a"));
+        second.record_invalid_utf8(2);
+
+        first.merge(second);
+
+        assert_eq!(first.total_requests(), 5);
+        assert_eq!(first.unique_ips(), 3);
+        // The IP only the second collector saw was moved, sharing the first's path copy
+        let shared = |ip: &str| {
+            let ip: IpAddr = ip.parse().unwrap();
+            first.stats[&ip].paths.keys().next().unwrap().clone()
+        };
+        assert!(Arc::ptr_eq(&shared("10.0.0.3"), &shared("10.0.0.2")));
+        let top = first.get_top_ips(1);
+        assert_eq!(top[0].0.to_string(), "10.0.0.1");
+        assert_eq!(top[0].1.request_count, 3);
+        assert_eq!(top[0].1.paths.get("/a"), Some(&2));
+        assert_eq!(
+            first.errors(),
+            ErrorCounts {
+                malformed: 1,
+                invalid_utf8: 2,
+                no_client_ip: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_stats_collector_aggregates_on_resolved_ip() {
+        use crate::client_ip::Cidr;
+        use crate::headers::HeaderValue;
+
+        let entry = |forwarded_for: &str| {
+            let mut entry = TraefikLogEntry {
+                client_host: Some("10.0.0.1".to_string()),
+                ..Default::default()
+            };
+            let value = HeaderValue::Text(forwarded_for.to_string());
+            entry.headers.request.insert("X-Forwarded-For", value);
+            entry
+        };
+
+        let resolver = IpResolver::new(vec![Cidr::parse("10.0.0.0/8").unwrap()]);
+        let mut collector = StatsCollector::with_resolver(resolver);
+        collector.add_entry(&entry("203.0.113.1"));
+        collector.add_entry(&entry("203.0.113.1, 10.0.0.2"));
+        collector.add_entry(&entry("198.51.100.4"));
+        collector.add_entry(&entry("garbage"));
+
+        assert_eq!(collector.unique_ips(), 3);
+        let top = collector.get_top_ips(1);
+        assert_eq!(top[0].0.to_string(), "203.0.113.1");
+        assert_eq!(top[0].1.request_count, 2);
+        assert_eq!(
+            collector.get_ip_sources(),
+            vec![(IpSource::Peer, 1), (IpSource::ForwardedFor, 3)]
+        );
+    }
+
+    #[test]
+    fn test_owned_and_borrowed_paths_count_the_same_lines() {
+        use crate::client_ip::Cidr;
+
+        let lines = [
+            r#"{"ClientHost":"10.0.0.1","RequestPath":"/a?x=1","StartUTC":"2024-01-15T10:30:00Z"}"#,
+            r#"{"ClientAddr":"[2001:db8::1]:443","StartLocal":"2024-01-15T11:30:00+01:00"}"#,
+            // Forwarding headers under any case, redacted, null or mis-typed
+            r#"{"ClientHost":"10.0.0.1","request_x-forwarded-for":"203.0.113.9"}"#,
+            r#"{"ClientHost":"10.0.0.1","request_X-REAL-IP":"198.51.100.2"}"#,
+            r#"{"ClientHost":"10.0.0.1","request_X-Forwarded-For":"203.0.113.9","request_x-forwarded-for":"REDACTED","request_X-Real-Ip":"198.51.100.7"}"#,
+            r#"{"ClientHost":"10.0.0.1","request_X-Forwarded-For":"203.0.113.9","request_X-Forwarded-For":null}"#,
+            r#"{"ClientHost":"10.0.0.1","request_X-Forwarded-For":5,"request_X-Real-Ip":["a"]}"#,
+            r#"{"ClientHost":"10.0.0.1","origin_X-Forwarded-For":"203.0.113.50"}"#,
+            r#"{"ClientHost":"10.0.0.9","request_X-Forwarded-For":"203.0.113.10"}"#,
+            // Mis-typed fields the statistics do not use
+            r#"{"ClientHost":"10.0.0.2","DownstreamStatus":"200","RequestMethod":7,"ServiceURL":5}"#,
+            // Malformed: mis-typed or repeated fields the statistics use, not an object
+            r#"{"ClientHost":"10.0.0.1","RequestPath":7}"#,
+            r#"{"ClientHost":"10.0.0.1","StartUTC":5}"#,
+            r#"{"ClientHost":"10.0.0.2","ClientHost":"10.0.0.3"}"#,
+            r#"["10.0.0.1"]"#,
+            "not json",
+            // No client IP
+            r#"{}"#,
+            r#"{"ClientHost":"unknown"}"#,
+        ];
+
+        let resolver = IpResolver::new(vec![Cidr::parse("10.0.0.0/8").unwrap()]);
+        let mut owned_stats = StatsCollector::with_resolver(resolver.clone());
+        let mut borrowed_stats = StatsCollector::with_resolver(resolver.clone());
+        for line in lines {
+            let owned = TraefikLogEntry::from_json_line(line);
+            let borrowed = BorrowedEntry::from_json_line(line);
+            match (owned, borrowed) {
+                (Ok(owned), Ok(borrowed)) => {
+                    assert_eq!(
+                        resolver.resolve(&owned),
+                        resolver.resolve_borrowed(&borrowed),
+                        "{}",
+                        line
+                    );
+                    assert_eq!(owned.get_path(), borrowed.get_path(), "{}", line);
+                    assert_eq!(owned.start_time(), borrowed.start_time(), "{}", line);
+                    owned_stats.add_entry(&owned);
+                    borrowed_stats.add_borrowed(&borrowed, "test");
+                }
+                (Err(_), Err(_)) => {
+                    owned_stats.record_malformed();
+                    borrowed_stats.record_malformed();
+                }
+                (owned, borrowed) => panic!(
+                    "paths disagree on {}: owned {:?}, borrowed {:?}",
+                    line,
+                    owned.is_ok(),
+                    borrowed.is_ok()
+                ),
+            }
+        }
+
+        let counts = |stats: &StatsCollector| {
+            let mut ips: Vec<(IpAddr, usize)> = stats
+                .get_top_ips(usize::MAX)
+                .into_iter()
+                .map(|(ip, ip_stats, _)| (ip, ip_stats.request_count))
+                .collect();
+            ips.sort();
+            ips
+        };
+        assert_eq!(owned_stats.total_requests(), 10);
+        assert_eq!(
+            owned_stats.total_requests(),
+            borrowed_stats.total_requests()
+        );
+        assert_eq!(owned_stats.errors(), borrowed_stats.errors());
+        assert_eq!(owned_stats.errors().malformed, 5);
+        assert_eq!(owned_stats.errors().no_client_ip, 2);
+        assert_eq!(counts(&owned_stats), counts(&borrowed_stats));
+        assert_eq!(
+            owned_stats.get_ip_sources(),
+            borrowed_stats.get_ip_sources()
+        );
+    }
+
+    #[test]
+    fn test_paths_are_interned_across_ips() {
+        let entry = |ip: &str| TraefikLogEntry {
+            client_host: Some(ip.to_string()),
+            request_path: Some("/api/users".to_string()),
+            ..Default::default()
+        };
+
+        let mut collector = StatsCollector::new();
+        collector.add_entry(&entry("10.0.0.1"));
+        collector.add_entry(&entry("10.0.0.2"));
+        collector.add_entry(&entry("10.0.0.2"));
+
+        let keys: Vec<&Arc<str>> = collector
+            .stats
+            .values()
+            .flat_map(|stats| stats.paths.keys())
+            .collect();
+        assert_eq!(keys.len(), 2);
+        assert!(Arc::ptr_eq(keys[0], keys[1]));
+    }
+
+    #[test]
+    fn test_paths_grouped_by_template() {
+        let entry = |path: &str| TraefikLogEntry {
+            client_host: Some("10.0.0.1".to_string()),
+            request_path: Some(path.to_string()),
+            ..Default::default()
+        };
+
+        let mut collector = StatsCollector::new().with_normalizer(PathNormalizer::default());
+        collector.add_entry(&entry("/api/users/123?x=1"));
+        collector.add_entry(&entry("/api/users/456"));
+        collector.add_entry(&entry("/api/users"));
+
+        let top = collector.get_top_ips(1);
+        assert_eq!(
+            top[0].1.top_paths(3),
+            vec![
+                ("/api/users/{id}".to_string(), 2),
+                ("/api/users".to_string(), 1)
+            ]
+        );
+    }
 }
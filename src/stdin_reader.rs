@@ -0,0 +1,184 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::sync::mpsc::SyncSender;
+use std::thread::{self, JoinHandle};
+
+use crate::event::MonitorEvent;
+use crate::file_reader::decode_line;
+
+/// Source tag for entries read from stdin
+pub const STDIN_SOURCE: &str = "stdin";
+
+/// Most lines forwarded in one event
+const MAX_BATCH_LINES: usize = 1_000;
+
+/// Spawn a thread that forwards every non-empty line of `reader` to the monitoring loop
+/// Used for piped input such as `docker logs -f traefik | traefik_log_parser -`.
+/// The thread ends at end of input or when the receiving side is dropped.
+pub fn spawn_line_reader<R>(reader: R, source: &str, tx: SyncSender<MonitorEvent>) -> JoinHandle<()>
+where
+    R: Read + Send + 'static,
+{
+    let source = source.to_string();
+    thread::spawn(move || {
+        forward_lines(reader, &source, &tx);
+    })
+}
+
+/// Forward every non-empty line of `reader` to the monitoring loop until end of input
+/// Lines are sent in batches of whatever has already been read, so a fast producer
+/// sends one event per read rather than one per line, while a slow one still has each
+/// line forwarded as soon as it arrives. The end of input is reported with
+/// `MonitorEvent::SourceClosed`, so the loop can drop the source's decoding state.
+/// Returns false if reading stopped because the receiving side was dropped
+pub fn forward_lines<R: Read>(reader: R, source: &str, tx: &SyncSender<MonitorEvent>) -> bool {
+    let mut reader = BufReader::new(reader);
+    let mut lines = Vec::new();
+    let mut invalid_utf8 = 0;
+
+    loop {
+        // Read raw bytes, so invalid UTF-8 never ends the input
+        let mut bytes = Vec::new();
+        let at_end = matches!(reader.read_until(b'\n', &mut bytes), Ok(0) | Err(_));
+        if !at_end {
+            let (line, invalid) = decode_line(bytes);
+            invalid_utf8 += invalid as usize;
+
+            // Trim whitespace and skip empty lines
+            let trimmed = line.trim();
+            if !trimmed.is_empty() {
+                lines.push(trimmed.to_string());
+            }
+        }
+
+        // Send once nothing more is buffered, the next read could block
+        let batch_done = reader.buffer().is_empty() || lines.len() >= MAX_BATCH_LINES;
+        if (at_end || batch_done) && (!lines.is_empty() || invalid_utf8 > 0) {
+            let event = MonitorEvent::Lines {
+                source: source.to_string(),
+                lines: std::mem::take(&mut lines),
+                invalid_utf8: std::mem::take(&mut invalid_utf8),
+            };
+            if tx.send(event).is_err() {
+                return false; // Main thread has terminated
+            }
+        }
+        if at_end {
+            return tx
+                .send(MonitorEvent::SourceClosed(source.to_string()))
+                .is_ok();
+        }
+    }
+}
+
+/// Open the controlling terminal for reading interactive commands
+/// Needed when stdin carries log lines, so commands must come from the TTY instead
+#[cfg(unix)]
+pub fn open_terminal() -> io::Result<Box<dyn BufRead + Send>> {
+    let tty = File::open("/dev/tty")?;
+    Ok(Box::new(BufReader::new(tty)))
+}
+
+/// Open the console input buffer for reading interactive commands
+/// Needed when stdin carries log lines, so commands must come from the console instead
+#[cfg(windows)]
+pub fn open_terminal() -> io::Result<Box<dyn BufRead + Send>> {
+    let console = File::open("CONIN$")?;
+    Ok(Box::new(BufReader::new(console)))
+}
+
+/// No controlling terminal is available on this platform
+#[cfg(not(any(unix, windows)))]
+pub fn open_terminal() -> io::Result<Box<dyn BufRead + Send>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "no controlling terminal",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_line_reader_batches_buffered_lines() {
+        let input: String = (0..2500).map(|i| format!("line {}\n", i)).collect();
+        let (tx, rx) = mpsc::sync_channel(100);
+
+        spawn_line_reader(Cursor::new(input), STDIN_SOURCE, tx)
+            .join()
+            .unwrap();
+
+        let batches: Vec<usize> = rx
+            .iter()
+            .filter_map(|event| match event {
+                MonitorEvent::Lines { lines, .. } => Some(lines.len()),
+                MonitorEvent::SourceClosed(_) => None,
+                other => panic!("unexpected event: {:?}", other),
+            })
+            .collect();
+        assert_eq!(batches.iter().sum::<usize>(), 2500);
+        assert!(batches.len() < 100);
+        assert!(batches.iter().all(|&len| len <= MAX_BATCH_LINES));
+    }
+
+    #[test]
+    fn test_line_reader_waits_for_a_full_queue() {
+        let input: String = (0..2500).map(|i| format!("line {}\n", i)).collect();
+        let (tx, rx) = mpsc::sync_channel(1);
+
+        // With nobody receiving, the reader blocks instead of queueing the input
+        let reader = spawn_line_reader(Cursor::new(input), STDIN_SOURCE, tx);
+        thread::sleep(std::time::Duration::from_millis(200));
+        assert!(!reader.is_finished());
+
+        let forwarded: usize = rx
+            .iter()
+            .map(|event| match event {
+                MonitorEvent::Lines { lines, .. } => lines.len(),
+                MonitorEvent::SourceClosed(_) => 0,
+                other => panic!("unexpected event: {:?}", other),
+            })
+            .sum();
+        assert_eq!(forwarded, 2500);
+        reader.join().unwrap();
+    }
+
+    #[test]
+    fn test_line_reader_forwards_non_empty_lines() {
+        let input = Cursor::new(
+            b"{\"ClientHost\":\"10.0.0.1\"}\n\n  \n{\"RequestPath\":\"/\xff\"}\n{\"ClientHost\":\"10.0.0.2\"}\n"
+                .to_vec(),
+        );
+        let (tx, rx) = mpsc::sync_channel(100);
+
+        spawn_line_reader(input, STDIN_SOURCE, tx).join().unwrap();
+
+        // The end of input is reported after the last lines
+        let mut events: Vec<_> = rx.iter().collect();
+        assert_eq!(
+            events.pop(),
+            Some(MonitorEvent::SourceClosed(STDIN_SOURCE.to_string()))
+        );
+        let lines: Vec<String> = events
+            .into_iter()
+            .flat_map(|event| match event {
+                MonitorEvent::Lines { source, lines, .. } => {
+                    assert_eq!(source, STDIN_SOURCE);
+                    lines
+                }
+                other => panic!("unexpected event: {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "{\"ClientHost\":\"10.0.0.1\"}",
+                "{\"RequestPath\":\"/\u{FFFD}\"}",
+                "{\"ClientHost\":\"10.0.0.2\"}"
+            ]
+        );
+    }
+}
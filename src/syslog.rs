@@ -0,0 +1,404 @@
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Read};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::SyncSender;
+use std::thread;
+use std::time::Duration;
+
+use crate::event::MonitorEvent;
+use crate::file_reader::decode_line;
+
+/// Largest syslog datagram accepted over UDP
+const MAX_DATAGRAM: usize = 65_535;
+/// Largest syslog message accepted over TCP; a connection sending a larger one is closed
+const MAX_FRAME: u64 = 64 * 1024;
+/// Digits of the largest octet count, plus the space that ends it
+const MAX_LENGTH_PREFIX: u64 = 6;
+/// TCP connections read at the same time; further ones are refused until one closes
+const MAX_TCP_CONNECTIONS: usize = 64;
+/// Pause after a failed UDP receive, doubled on every further failure
+const RECV_RETRY_DELAY: Duration = Duration::from_millis(10);
+/// Longest pause between UDP receive retries
+const MAX_RECV_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// A syslog message with its header stripped
+#[derive(Debug, PartialEq)]
+pub struct SyslogMessage<'a> {
+    /// HOSTNAME field of the header, if the sender filled it in
+    pub host: Option<&'a str>,
+    /// Message content, i.e. the Traefik JSON line
+    pub message: &'a str,
+}
+
+/// Strip the header off an RFC 5424 or RFC 3164 (BSD) syslog message
+/// RFC 5424: `<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID [SD] MSG`
+/// RFC 3164: `<PRI>Mmm dd hh:mm:ss HOSTNAME TAG: MSG` (HOSTNAME is often omitted locally)
+/// Returns None if the line has no `<PRI>` header
+pub fn parse_syslog(line: &str) -> Option<SyslogMessage<'_>> {
+    let rest = line.strip_prefix('<')?;
+    let (pri, rest) = rest.split_once('>')?;
+    if pri.is_empty() || pri.len() > 3 || !pri.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    match rest.split_once(' ') {
+        Some((version, after))
+            if !version.is_empty() && version.bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            Some(parse_rfc5424(after))
+        }
+        _ => Some(parse_rfc3164(rest)),
+    }
+}
+
+/// Parse the part of an RFC 5424 message after the version
+fn parse_rfc5424(rest: &str) -> SyslogMessage<'_> {
+    // TIMESTAMP HOSTNAME APP-NAME PROCID MSGID, each "-" when absent
+    let mut fields = rest.splitn(6, ' ');
+    let _timestamp = fields.next();
+    let host = fields.next().filter(|host| *host != "-");
+    let _app_name = fields.next();
+    let _proc_id = fields.next();
+    let _msg_id = fields.next();
+    let rest = fields.next().unwrap_or("");
+
+    let message = skip_structured_data(rest);
+    let message = message.strip_prefix('\u{FEFF}').unwrap_or(message);
+    SyslogMessage { host, message }
+}
+
+/// Skip the STRUCTURED-DATA field ("-" or one or more `[...]` elements)
+fn skip_structured_data(rest: &str) -> &str {
+    if let Some(message) = rest.strip_prefix('-') {
+        return message.strip_prefix(' ').unwrap_or(message);
+    }
+
+    let bytes = rest.as_bytes();
+    let mut i = 0;
+    while bytes.get(i) == Some(&b'[') {
+        // Inside an element, `]` may be escaped with a backslash
+        i += 1;
+        while i < bytes.len() && bytes[i] != b']' {
+            i += if bytes[i] == b'\\' { 2 } else { 1 };
+        }
+        i += 1;
+    }
+    let message = rest.get(i.min(rest.len())..).unwrap_or("");
+    message.strip_prefix(' ').unwrap_or(message)
+}
+
+/// Parse the part of an RFC 3164 message after the priority
+fn parse_rfc3164(rest: &str) -> SyslogMessage<'_> {
+    // "Mmm dd hh:mm:ss " is fixed width
+    let rest = match rest.get(..16) {
+        Some(timestamp) if timestamp.as_bytes()[3] == b' ' && timestamp.ends_with(' ') => {
+            &rest[16..]
+        }
+        _ => rest,
+    };
+
+    // The hostname is omitted when the next word is already the tag ("traefik:" or "traefik[1]:")
+    let (host, rest) = match rest.split_once(' ') {
+        Some((word, after)) if !is_tag(word) && !word.starts_with('{') => (Some(word), after),
+        _ => (None, rest),
+    };
+
+    let message = match rest.split_once(' ') {
+        Some((tag, after)) if is_tag(tag) => after,
+        _ => rest,
+    };
+    SyslogMessage { host, message }
+}
+
+/// Check whether a word is a syslog TAG such as `traefik:` or `traefik[42]:`
+fn is_tag(word: &str) -> bool {
+    word.len() > 1 && word.ends_with(':') && !word.starts_with('{')
+}
+
+/// Turn one received syslog message into a monitor event
+/// The source tag is the sender's HOSTNAME, or its address if the header has none.
+/// Lines without a syslog header are passed on unchanged.
+fn to_event(bytes: Vec<u8>, peer: &SocketAddr) -> Option<MonitorEvent> {
+    let (line, invalid) = decode_line(bytes);
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+
+    let (host, message) = match parse_syslog(line) {
+        Some(parsed) => (parsed.host, parsed.message.trim()),
+        None => (None, line),
+    };
+    let source = host.map_or_else(|| peer.ip().to_string(), str::to_string);
+
+    Some(MonitorEvent::Lines {
+        source,
+        lines: vec![message.to_string()],
+        invalid_utf8: invalid as usize,
+    })
+}
+
+/// Listen for syslog messages over UDP and TCP on the same address
+/// UDP carries one message per datagram; TCP connections may use newline-delimited
+/// or octet-counted framing (RFC 6587), with messages up to `MAX_FRAME` bytes and at
+/// most `MAX_TCP_CONNECTIONS` at a time. Returns the bound address, so port 0 can be used.
+pub fn spawn_syslog_listener(addr: &str, tx: SyncSender<MonitorEvent>) -> Result<SocketAddr> {
+    let udp =
+        UdpSocket::bind(addr).context(format!("Failed to bind syslog UDP socket: {}", addr))?;
+    let local_addr = udp.local_addr().context("Failed to read syslog address")?;
+    let tcp = TcpListener::bind(local_addr)
+        .context(format!("Failed to bind syslog TCP socket: {}", local_addr))?;
+
+    let udp_tx = tx.clone();
+    thread::spawn(move || {
+        let mut buffer = vec![0; MAX_DATAGRAM];
+        let mut retry_delay = RECV_RETRY_DELAY;
+        loop {
+            let (len, peer) = match udp.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(e) => {
+                    // Back off, so a persistent error does not spin a core
+                    eprintln!("Warning: Failed to receive syslog datagram: {}", e);
+                    thread::sleep(retry_delay);
+                    retry_delay = (retry_delay * 2).min(MAX_RECV_RETRY_DELAY);
+                    continue;
+                }
+            };
+            retry_delay = RECV_RETRY_DELAY;
+            if let Some(event) = to_event(buffer[..len].to_vec(), &peer)
+                && udp_tx.send(event).is_err()
+            {
+                break; // Main thread has terminated
+            }
+        }
+    });
+
+    thread::spawn(move || {
+        let connections = Arc::new(AtomicUsize::new(0));
+        for stream in tcp.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if connections.load(Ordering::SeqCst) >= MAX_TCP_CONNECTIONS {
+                        eprintln!(
+                            "Warning: Refusing syslog connection, {} already open",
+                            MAX_TCP_CONNECTIONS
+                        );
+                        continue;
+                    }
+                    connections.fetch_add(1, Ordering::SeqCst);
+                    let (tx, connections) = (tx.clone(), connections.clone());
+                    thread::spawn(move || {
+                        // Hosts seen on the connection are closed with it
+                        let mut sources = HashSet::new();
+                        read_tcp_stream(stream, &tx, &mut sources);
+                        for source in sources {
+                            let _ = tx.send(MonitorEvent::SourceClosed(source));
+                        }
+                        connections.fetch_sub(1, Ordering::SeqCst);
+                    });
+                }
+                Err(e) => eprintln!("Warning: Failed to accept syslog connection: {}", e),
+            }
+        }
+    });
+
+    Ok(local_addr)
+}
+
+/// Read framed syslog messages from one TCP connection until it closes
+/// The source tags of the messages read are added to `sources`
+fn read_tcp_stream(
+    stream: TcpStream,
+    tx: &SyncSender<MonitorEvent>,
+    sources: &mut HashSet<String>,
+) {
+    let Ok(peer) = stream.peer_addr() else {
+        return;
+    };
+    let mut reader = BufReader::new(stream);
+
+    loop {
+        // Octet counting starts with the message length, plain framing with "<PRI>"
+        let octet_counted = match reader.fill_buf() {
+            Ok([]) | Err(_) => return, // Connection closed
+            Ok(buffer) => buffer[0].is_ascii_digit(),
+        };
+
+        // Every read is bounded, so a sender cannot make us buffer without limit
+        let mut message = Vec::new();
+        if octet_counted {
+            let mut length = Vec::new();
+            if (&mut reader)
+                .take(MAX_LENGTH_PREFIX)
+                .read_until(b' ', &mut length)
+                .is_err()
+            {
+                return;
+            }
+            let Some(length) = std::str::from_utf8(&length)
+                .ok()
+                .and_then(|length| length.strip_suffix(' '))
+                .and_then(|length| length.parse::<u64>().ok())
+            else {
+                eprintln!("Warning: Invalid syslog frame length from {}", peer);
+                return;
+            };
+            if length > MAX_FRAME {
+                eprintln!(
+                    "Warning: Closing syslog connection from {}, {} byte frame exceeds {} bytes",
+                    peer, length, MAX_FRAME
+                );
+                return;
+            }
+            if (&mut reader)
+                .take(length)
+                .read_to_end(&mut message)
+                .is_err()
+            {
+                return;
+            }
+        } else {
+            if (&mut reader)
+                .take(MAX_FRAME + 1)
+                .read_until(b'\n', &mut message)
+                .is_err()
+            {
+                return;
+            }
+            if message.len() as u64 > MAX_FRAME && !message.ends_with(b"\n") {
+                eprintln!(
+                    "Warning: Closing syslog connection from {}, line exceeds {} bytes",
+                    peer, MAX_FRAME
+                );
+                return;
+            }
+        }
+
+        let Some(event) = to_event(message, &peer) else {
+            continue;
+        };
+        if let MonitorEvent::Lines { ref source, .. } = event
+            && !sources.contains(source)
+        {
+            sources.insert(source.clone());
+        }
+        if tx.send(event).is_err() {
+            return; // Main thread has terminated
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    const TRAEFIK_LINE: &str = r#"{"ClientHost":"10.0.0.1","RequestPath":"/api"}"#;
+
+    #[test]
+    fn test_parse_rfc5424() {
+        let line = format!(
+            "<134>1 2024-01-15T10:00:00.123Z edge-1 traefik 42 - - {}",
+            TRAEFIK_LINE
+        );
+        let parsed = parse_syslog(&line).unwrap();
+        assert_eq!(parsed.host, Some("edge-1"));
+        assert_eq!(parsed.message, TRAEFIK_LINE);
+
+        // Structured data (with an escaped bracket) and a BOM before the message
+        let line = format!(
+            "<134>1 - - traefik - access [meta x=\"a\\]b\"][origin ip=\"10.1.1.1\"] \u{FEFF}{}",
+            TRAEFIK_LINE
+        );
+        let parsed = parse_syslog(&line).unwrap();
+        assert_eq!(parsed.host, None);
+        assert_eq!(parsed.message, TRAEFIK_LINE);
+    }
+
+    #[test]
+    fn test_parse_rfc3164() {
+        let line = format!("<134>Jan  5 10:00:00 edge-2 traefik[7]: {}", TRAEFIK_LINE);
+        let parsed = parse_syslog(&line).unwrap();
+        assert_eq!(parsed.host, Some("edge-2"));
+        assert_eq!(parsed.message, TRAEFIK_LINE);
+
+        // Local senders often leave out the hostname
+        let line = format!("<14>Jan 15 10:00:00 traefik: {}", TRAEFIK_LINE);
+        let parsed = parse_syslog(&line).unwrap();
+        assert_eq!(parsed.host, None);
+        assert_eq!(parsed.message, TRAEFIK_LINE);
+
+        assert!(parse_syslog(TRAEFIK_LINE).is_none());
+    }
+
+    #[test]
+    fn test_loopback_udp_and_tcp() {
+        let (tx, rx) = mpsc::sync_channel(16);
+        let addr = spawn_syslog_listener("127.0.0.1:0", tx).unwrap();
+        let next = || match rx.recv_timeout(Duration::from_secs(5)).unwrap() {
+            MonitorEvent::Lines { source, lines, .. } => (source, lines),
+            other => panic!("unexpected event: {:?}", other),
+        };
+
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let message = format!(
+            "<134>1 2024-01-15T10:00:00Z edge-1 traefik - - - {}",
+            TRAEFIK_LINE
+        );
+        sender.send_to(message.as_bytes(), addr).unwrap();
+        assert_eq!(
+            next(),
+            ("edge-1".to_string(), vec![TRAEFIK_LINE.to_string()])
+        );
+
+        // Newline framing, then octet counting, on one connection; no hostname falls
+        // back to the sender's address
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let message = format!("<14>Jan 15 10:00:00 traefik: {}", TRAEFIK_LINE);
+        write!(stream, "{}\n{} {}", message, message.len(), message).unwrap();
+        assert_eq!(
+            next(),
+            ("127.0.0.1".to_string(), vec![TRAEFIK_LINE.to_string()])
+        );
+        assert_eq!(
+            next(),
+            ("127.0.0.1".to_string(), vec![TRAEFIK_LINE.to_string()])
+        );
+
+        // Closing the connection closes the sources it carried
+        drop(stream);
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(5)).unwrap(),
+            MonitorEvent::SourceClosed("127.0.0.1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_oversized_tcp_frame_closes_connection() {
+        let (tx, rx) = mpsc::sync_channel(16);
+        let addr = spawn_syslog_listener("127.0.0.1:0", tx).unwrap();
+
+        // The frame is refused as soon as its length is known
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let header = format!("{} ", MAX_FRAME + 1);
+        stream.write_all(header.as_bytes()).unwrap();
+        let mut buffer = [0; 1];
+        assert_eq!(stream.read(&mut buffer).unwrap(), 0);
+
+        // A frame within the limit is still accepted on a new connection
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "{} {}", TRAEFIK_LINE.len(), TRAEFIK_LINE).unwrap();
+        match rx.recv_timeout(Duration::from_secs(5)).unwrap() {
+            MonitorEvent::Lines { lines, .. } => assert_eq!(lines, vec![TRAEFIK_LINE]),
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+}
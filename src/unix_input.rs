@@ -0,0 +1,248 @@
+use anyhow::{Context, Result, bail};
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::fs::{self, OpenOptions};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::mpsc::SyncSender;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::event::MonitorEvent;
+use crate::stdin_reader::forward_lines;
+
+/// Spawn a thread that reads newline-delimited log lines from a named pipe
+/// The FIFO is created if it does not exist yet. It is opened for writing too, so it
+/// never reports end of input: when the writer closes its end (e.g. Traefik restarts),
+/// reading simply resumes with the next writer, and nothing written in between is lost.
+/// Lines are tagged with the FIFO's path.
+pub fn spawn_fifo_reader(path: &Path, tx: SyncSender<MonitorEvent>) -> Result<JoinHandle<()>> {
+    match fs::metadata(path) {
+        Ok(meta) if meta.file_type().is_fifo() => {}
+        Ok(_) => bail!("Not a named pipe: {}", path.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => make_fifo(path)?,
+        Err(e) => return Err(e).context(format!("Failed to access FIFO: {}", path.display())),
+    }
+
+    // Opening read-only would block until a writer appears, and would see end of input
+    // whenever the last writer leaves
+    let fifo = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .context(format!("Failed to open FIFO: {}", path.display()))?;
+    let source = path.display().to_string();
+    Ok(thread::spawn(move || {
+        forward_lines(fifo, &source, &tx);
+    }))
+}
+
+/// Spawn a thread that listens on a Unix stream socket for log lines
+/// Any number of writers may connect at once; each connection is read on its own
+/// thread. A stale socket file left behind by an earlier run is replaced. Lines are
+/// tagged with the socket's path and the writer's process ID where the platform reports
+/// it (`access.sock (pid 812)`), so a writer that reconnects keeps its source. Another
+/// connection open under the same tag is numbered (`access.sock (pid 812)#2`).
+pub fn spawn_socket_listener(path: &Path, tx: SyncSender<MonitorEvent>) -> Result<JoinHandle<()>> {
+    if let Ok(meta) = fs::symlink_metadata(path) {
+        if !meta.file_type().is_socket() {
+            bail!("Not a socket: {}", path.display());
+        }
+        fs::remove_file(path)
+            .context(format!("Failed to remove stale socket: {}", path.display()))?;
+    }
+    let listener = UnixListener::bind(path)
+        .context(format!("Failed to listen on socket: {}", path.display()))?;
+
+    let socket = path.display().to_string();
+    Ok(thread::spawn(move || {
+        // Tags of the connections currently open
+        let open = Arc::new(Mutex::new(HashSet::new()));
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("Warning: Failed to accept connection on {}: {}", socket, e);
+                    continue;
+                }
+            };
+            let tag = match peer_pid(&stream) {
+                Some(pid) => format!("{} (pid {})", socket, pid),
+                None => socket.clone(),
+            };
+            let source = claim_source(&mut open.lock().unwrap(), tag);
+            let (tx, open) = (tx.clone(), open.clone());
+            thread::spawn(move || {
+                // The tag is released after the close was sent, so a connection
+                // reusing it starts with fresh state
+                forward_lines(stream, &source, &tx);
+                open.lock().unwrap().remove(&source);
+            });
+        }
+    }))
+}
+
+/// Reserve a source tag for a new connection, numbering it if the tag is already open
+fn claim_source(open: &mut HashSet<String>, tag: String) -> String {
+    let source = if open.contains(&tag) {
+        (2..)
+            .map(|number| format!("{}#{}", tag, number))
+            .find(|numbered| !open.contains(numbered))
+            .expect("numbers are unbounded")
+    } else {
+        tag
+    };
+    open.insert(source.clone());
+    source
+}
+
+/// Process ID of the writer on the other end of a socket connection
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_pid(stream: &UnixStream) -> Option<libc::pid_t> {
+    use std::os::fd::AsRawFd;
+
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: cred and len point to live values of the sizes passed
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut cred as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    (result == 0 && cred.pid > 0).then_some(cred.pid)
+}
+
+/// The writer's process ID is not available on this platform
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_pid(_stream: &UnixStream) -> Option<libc::pid_t> {
+    None
+}
+
+/// Create a named pipe readable and writable by the owner
+fn make_fifo(path: &Path) -> Result<()> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .context(format!("Invalid FIFO path: {}", path.display()))?;
+    // SAFETY: c_path is a valid NUL-terminated string for the duration of the call
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .context(format!("Failed to create FIFO: {}", path.display()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    /// Source and first line of the next event
+    fn next_line(rx: &mpsc::Receiver<MonitorEvent>) -> (String, String) {
+        match rx.recv_timeout(Duration::from_secs(5)).unwrap() {
+            MonitorEvent::Lines {
+                source, mut lines, ..
+            } => (source, lines.remove(0)),
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_fifo_survives_writer_reconnects() {
+        let fifo = Path::new("test_unix_input.fifo");
+        fs::remove_file(fifo).ok();
+
+        let (tx, rx) = mpsc::sync_channel(16);
+        spawn_fifo_reader(fifo, tx).unwrap();
+        assert!(fs::metadata(fifo).unwrap().file_type().is_fifo());
+
+        // Each writer connects, writes and disconnects
+        for line in ["first writer", "second writer"] {
+            let mut writer = fs::OpenOptions::new().write(true).open(fifo).unwrap();
+            writeln!(writer, "{}", line).unwrap();
+            drop(writer);
+            assert_eq!(next_line(&rx).1, line);
+        }
+
+        // Clean up
+        fs::remove_file(fifo).ok();
+    }
+
+    #[test]
+    fn test_closed_tags_are_reused() {
+        let mut open = HashSet::new();
+        assert_eq!(
+            claim_source(&mut open, "s (pid 1)".to_string()),
+            "s (pid 1)"
+        );
+        assert_eq!(
+            claim_source(&mut open, "s (pid 1)".to_string()),
+            "s (pid 1)#2"
+        );
+        assert_eq!(
+            claim_source(&mut open, "s (pid 2)".to_string()),
+            "s (pid 2)"
+        );
+
+        // A closed tag is handed out again, so reconnects do not add sources
+        open.remove("s (pid 1)");
+        assert_eq!(
+            claim_source(&mut open, "s (pid 1)".to_string()),
+            "s (pid 1)"
+        );
+        assert_eq!(
+            claim_source(&mut open, "s (pid 1)".to_string()),
+            "s (pid 1)#3"
+        );
+    }
+
+    #[test]
+    fn test_socket_tags_each_writer() {
+        let socket = Path::new("test_unix_input.sock");
+        // A leftover socket file from an earlier run is replaced
+        drop(UnixListener::bind(socket));
+
+        let (tx, rx) = mpsc::sync_channel(16);
+        spawn_socket_listener(socket, tx).unwrap();
+
+        let mut first = UnixStream::connect(socket).unwrap();
+        let mut second = UnixStream::connect(socket).unwrap();
+        writeln!(second, "from second").unwrap();
+        let (second_source, line) = next_line(&rx);
+        assert_eq!(line, "from second");
+        writeln!(first, "from first").unwrap();
+        let (first_source, line) = next_line(&rx);
+        assert_eq!(line, "from first");
+
+        // Both writers are this process; the second connection open under its tag
+        // is numbered
+        let tag = if cfg!(any(target_os = "linux", target_os = "android")) {
+            format!("test_unix_input.sock (pid {})", std::process::id())
+        } else {
+            "test_unix_input.sock".to_string()
+        };
+        assert_eq!(first_source, tag);
+        assert_eq!(second_source, format!("{}#2", tag));
+
+        // Closing a connection is reported with its tag
+        drop(first);
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(5)).unwrap(),
+            MonitorEvent::SourceClosed(tag)
+        );
+
+        // Clean up
+        fs::remove_file(socket).ok();
+    }
+}